@@ -0,0 +1,164 @@
+use std::f64::consts::TAU;
+
+use super::*;
+use crate::fft::{fft, Complex};
+
+/// `Float` はどのチャンネルにもそのまま使う．
+pub fn channel(values: &[Value], channel: usize) -> Result<Vec<f64>, Error> {
+    values
+        .iter()
+        .enumerate()
+        .map(|(pos, value)| match value {
+            Value::Float(x) => Ok(*x),
+            Value::Frame(frame) => frame
+                .get(channel)
+                .copied()
+                .ok_or(Error::Channel(channel, frame.len())),
+            value => Err(Error::NotFloat(pos, value.clone())),
+        })
+        .collect()
+}
+
+pub fn rms(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    (samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+pub fn dc(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    samples.iter().sum::<f64>() / samples.len() as f64
+}
+
+#[derive(Clone, Debug)]
+pub struct Spectrum {
+    pub rate: f64,
+    pub size: usize,
+    /// 0 Hz からナイキスト周波数までの `size / 2 + 1` 個のビンの振幅．
+    /// 振幅 1 の正弦波がビンの中心にあれば 1 になる．
+    pub magnitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    /// ハン窓をかけて変換する．長さは次の 2 のべきまで 0 で埋める．
+    pub fn new(samples: &[f64], rate: f64) -> Spectrum {
+        let size = samples.len().max(2).next_power_of_two();
+        let len = samples.len() as f64;
+        let mut data = vec![Complex::default(); size];
+        let mut gain = 0.;
+        for (i, (slot, x)) in data.iter_mut().zip(samples).enumerate() {
+            let window = 0.5 - 0.5 * (TAU * i as f64 / len).cos();
+            slot.re = x * window;
+            gain += window;
+        }
+        fft(&mut data, false);
+        let magnitudes = data[..=size / 2]
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                // 直流とナイキスト周波数以外は負の周波数の分を足す
+                let scale = if i == 0 || i == size / 2 { 1. } else { 2. };
+                x.norm() * scale / gain.max(f64::MIN_POSITIVE)
+            })
+            .collect();
+        Spectrum {
+            rate,
+            size,
+            magnitudes,
+        }
+    }
+    pub fn freq(&self, i: usize) -> f64 {
+        i as f64 * self.rate / self.size as f64
+    }
+    pub fn magnitude(&self, freq: f64) -> f64 {
+        let i = (freq * self.size as f64 / self.rate).round().max(0.) as usize;
+        self.magnitudes.get(i).copied().unwrap_or(0.)
+    }
+    pub fn peak(&self) -> Option<(f64, f64)> {
+        self.peaks(1).into_iter().next()
+    }
+    /// 極大を振幅の大きい順に `n` 個まで．直流が窓で漏れる最初の 2 つのビンは除く．
+    /// 周波数は前後のビンとの放物線補間で求める．
+    pub fn peaks(&self, n: usize) -> Vec<(f64, f64)> {
+        let m = &self.magnitudes;
+        let mut peaks: Vec<_> = (2..m.len())
+            .filter(|&i| m[i] > m[i - 1] && m.get(i + 1).is_none_or(|&next| m[i] >= next))
+            .map(|i| {
+                let Some(&next) = m.get(i + 1) else {
+                    return (self.freq(i), m[i]);
+                };
+                let (a, b, c) = (m[i - 1], m[i], next);
+                let denom = a - 2. * b + c;
+                let offset = if denom == 0. {
+                    0.
+                } else {
+                    0.5 * (a - c) / denom
+                };
+                (
+                    (i as f64 + offset) * self.rate / self.size as f64,
+                    b - 0.25 * (a - c) * offset,
+                )
+            })
+            .collect();
+        peaks.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        peaks.truncate(n);
+        peaks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 8000.;
+
+    fn sine(freq: f64, offset: f64, n: usize) -> Vec<f64> {
+        let sound = Sound::App(
+            Func::Add,
+            vec![
+                Arc::new(Sound::Const(Box::new(Value::Float(offset)))),
+                Arc::new(Sound::osc(
+                    Func::Sin,
+                    Arc::new(Sound::Const(Box::new(Value::Float(freq)))),
+                )),
+            ],
+        );
+        channel(&sound.sample(RATE, n).unwrap(), 0).unwrap()
+    }
+
+    #[test]
+    fn peak_of_sine() {
+        let spectrum = Spectrum::new(&sine(440., 0., 4096), RATE);
+        assert_eq!(spectrum.size, 4096);
+        let (freq, magnitude) = spectrum.peak().unwrap();
+        assert!((freq - 440.).abs() < 1., "{freq}");
+        assert!((magnitude - 1.).abs() < 0.05, "{magnitude}");
+        assert!(spectrum.magnitude(1000.) < 1e-3);
+    }
+
+    #[test]
+    fn rms_and_dc() {
+        // 8000 サンプルは 440 Hz のちょうど 440 周期
+        let samples = sine(440., 0., 8000);
+        assert!((rms(&samples) - 0.5f64.sqrt()).abs() < 1e-6);
+        assert!(dc(&samples).abs() < 1e-6);
+        let shifted = sine(440., 0.25, 8000);
+        assert!((dc(&shifted) - 0.25).abs() < 1e-6);
+        assert_eq!((rms(&[]), dc(&[])), (0., 0.));
+    }
+
+    #[test]
+    fn channels_of_values() {
+        let values = [Value::Float(0.5), Value::Frame(Arc::new([1., 2.]))];
+        assert_eq!(channel(&values, 1).unwrap(), [0.5, 2.]);
+        assert!(matches!(channel(&values, 2), Err(Error::Channel(2, 2))));
+        let values = [Value::Float(0.5), Value::Bool(true)];
+        assert!(matches!(
+            channel(&values, 0),
+            Err(Error::NotFloat(1, Value::Bool(true)))
+        ));
+    }
+}
@@ -0,0 +1,727 @@
+use std::fmt;
+
+use super::*;
+
+#[derive(Debug)]
+pub enum TypeError {
+    Local(usize),
+    NotFunc(ty::Ty),
+    /// 多相な組み込み関数を値として使おうとした．
+    Polymorphic(Func),
+    Builtin {
+        func: Func,
+        args: Vec<ty::Ty>,
+        reason: ty::Mismatch,
+    },
+    Call { func: ty::Ty, args: Vec<ty::Ty> },
+    /// `Sound::Concat` や `Sound::Mix` で並べる音の型が揃っていない．
+    Concat(Vec<ty::Ty>),
+    /// `Sound::Merge` に `Float` 以外の音を渡したか，`Sound::Channel` に `Frame` 以外の音を渡した．
+    Channel(ty::Ty),
+    /// `n` チャンネルのフレームの音から `i` 番目のチャンネルを取り出そうとした．
+    ChannelIndex(usize, usize),
+    Gate(ty::Ty),
+    Return { expected: ty::Ty, found: ty::Ty },
+    Nested(ty::Ty),
+    /// 区間の外を埋める無音がない型の音を切り出そうとした．
+    Silence(ty::Ty),
+    Mix(ty::Ty),
+    Window(f64, f64),
+    Scale(f64),
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::Local(pos) => write!(f, "local #{pos} is not defined"),
+            TypeError::NotFunc(ty) => write!(f, "a value of type {ty:?} is not a function"),
+            TypeError::Polymorphic(func) => {
+                write!(f, "polymorphic function {func:?} cannot be used as a value")
+            }
+            TypeError::Builtin { func, args, reason } => write!(
+                f,
+                "{func:?}: {:?} cannot be applied to ({}): {reason}",
+                func.signature(),
+                fmt_tys(args)
+            ),
+            TypeError::Call { func, args } => {
+                write!(f, "{func:?} cannot be applied to ({})", fmt_tys(args))
+            }
+            TypeError::Concat(tys) => write!(f, "cannot combine sounds of ({})", fmt_tys(tys)),
+            TypeError::Channel(ty) => write!(f, "a sound of {ty:?} is not a channel or a frame"),
+            TypeError::ChannelIndex(i, n) => {
+                write!(f, "channel #{i} of a sound of frames with {n} channel(s)")
+            }
+            TypeError::Gate(ty) => write!(f, "a sound of {ty:?} cannot be used as a gate"),
+            TypeError::Return { expected, found } => {
+                write!(
+                    f,
+                    "the body of a lambda returns {found:?}, expected {expected:?}"
+                )
+            }
+            TypeError::Nested(ty) => write!(f, "a sound of {ty:?} is not a sound of sounds"),
+            TypeError::Silence(ty) => write!(f, "a sound of {ty:?} has no silence to trim with"),
+            TypeError::Mix(ty) => write!(f, "sounds of {ty:?} cannot be mixed"),
+            TypeError::Window(from, to) => write!(f, "window [{from}, {to}) ends before it starts"),
+            TypeError::Scale(scale) => write!(f, "cannot stretch time by {scale}"),
+        }
+    }
+}
+impl std::error::Error for TypeError {}
+fn fmt_tys(tys: &[ty::Ty]) -> String {
+    tys.iter()
+        .map(|ty| format!("{ty:?}"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+impl Func {
+    pub fn signature(&self) -> ty::Func {
+        use ty::{Arg, Expr, Kind};
+        let float = || Arg::Expr(Expr::App(Kind::Float, vec![]));
+        // チャンネル数は範囲で表し，`AddFrame` の引数どうしで揃える
+        let frame = || Expr::App(Kind::Frame, vec![Arg::Expand(Expr::Range(0, 0))]);
+        let sound = |arg| Arg::Expr(Expr::App(Kind::Sound, vec![arg]));
+        let int = || Arg::Expr(Expr::App(Kind::Int, vec![]));
+        let bool = || Arg::Expr(Expr::App(Kind::Bool, vec![]));
+        match self {
+            Func::Sin | Func::Saw | Func::Square | Func::Triangle => ty::Func {
+                args: vec![float()],
+                ret: Expr::App(Kind::Float, vec![]),
+            },
+            Func::Add | Func::Sub | Func::Mul | Func::Div => ty::Func {
+                args: vec![float(), float()],
+                ret: Expr::App(Kind::Float, vec![]),
+            },
+            Func::AddInt | Func::SubInt | Func::MulInt | Func::DivInt | Func::RemInt => ty::Func {
+                args: vec![int(), int()],
+                ret: Expr::App(Kind::Int, vec![]),
+            },
+            Func::ToFloat => ty::Func {
+                args: vec![int()],
+                ret: Expr::App(Kind::Float, vec![]),
+            },
+            Func::Floor => ty::Func {
+                args: vec![float()],
+                ret: Expr::App(Kind::Int, vec![]),
+            },
+            Func::Less | Func::LessEq | Func::Equal => ty::Func {
+                args: vec![float(), float()],
+                ret: Expr::App(Kind::Bool, vec![]),
+            },
+            Func::LessInt | Func::LessEqInt | Func::EqualInt => ty::Func {
+                args: vec![int(), int()],
+                ret: Expr::App(Kind::Bool, vec![]),
+            },
+            Func::And | Func::Or => ty::Func {
+                args: vec![bool(), bool()],
+                ret: Expr::App(Kind::Bool, vec![]),
+            },
+            Func::Not => ty::Func {
+                args: vec![bool()],
+                ret: Expr::App(Kind::Bool, vec![]),
+            },
+            Func::If => ty::Func {
+                args: vec![bool(), Arg::Expr(Expr::Var(0)), Arg::Expr(Expr::Var(0))],
+                ret: Expr::Var(0),
+            },
+            Func::Pan => ty::Func {
+                args: vec![float(), float()],
+                ret: Expr::App(Kind::Frame, vec![float(), float()]),
+            },
+            Func::MixDown => ty::Func {
+                args: vec![Arg::Expr(frame())],
+                ret: Expr::App(Kind::Float, vec![]),
+            },
+            Func::AddFrame => ty::Func {
+                args: vec![Arg::Expr(frame()), Arg::Expr(frame())],
+                ret: frame(),
+            },
+            Func::App => ty::Func {
+                args: vec![
+                    Arg::Expr(Expr::App(
+                        Kind::Func,
+                        vec![Arg::Expr(Expr::Var(0)), Arg::Expand(Expr::Range(1, 0))],
+                    )),
+                    Arg::Expand(Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Range(1, 0))])),
+                ],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+            Func::Flatten | Func::Trigger => ty::Func {
+                args: vec![Arg::Expr(Expr::App(
+                    Kind::Sound,
+                    vec![Arg::Expr(Expr::App(
+                        Kind::Sound,
+                        vec![Arg::Expr(Expr::Var(0))],
+                    ))],
+                ))],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+            Func::Shift | Func::Stretch => ty::Func {
+                args: vec![
+                    float(),
+                    Arg::Expr(Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))])),
+                ],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+            Func::Trim => ty::Func {
+                args: vec![
+                    float(),
+                    float(),
+                    Arg::Expr(Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))])),
+                ],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+            Func::Adsr => ty::Func {
+                args: vec![
+                    float(),
+                    float(),
+                    float(),
+                    float(),
+                    Arg::Expr(Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))])),
+                ],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            Func::Noise(_) => ty::Func {
+                args: vec![int()],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            Func::Closure(closure) => ty::Func {
+                args: closure
+                    .params
+                    .iter()
+                    .map(|ty| Arg::Expr(ty.to_expr()))
+                    .collect(),
+                ret: closure.ret.to_expr(),
+            },
+            Func::Const => ty::Func {
+                args: vec![Arg::Expr(Expr::Var(0))],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+            Func::Integrate => ty::Func {
+                args: vec![sound(float())],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            Func::Osc => ty::Func {
+                args: vec![
+                    Arg::Expr(Expr::App(Kind::Func, vec![float(), float()])),
+                    sound(float()),
+                ],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            Func::OnePole | Func::Delay => ty::Func {
+                args: vec![float(), sound(float())],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            Func::Lowpass | Func::Highpass | Func::Bandpass | Func::Comb => ty::Func {
+                args: vec![float(), float(), sound(float())],
+                ret: Expr::App(Kind::Sound, vec![float()]),
+            },
+            // (長さ, 音) の組ひとつ分．`apply` で組の数だけ繰り返す
+            Func::Concat => ty::Func {
+                args: vec![float(), sound(Arg::Expr(Expr::Var(0)))],
+                ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+            },
+        }
+    }
+    /// 関数を値として使うときの型．型変数を含むシグネチャには定まらない．
+    fn ty(&self) -> Result<ty::Ty, TypeError> {
+        self.signature()
+            .mono()
+            .ok_or_else(|| TypeError::Polymorphic(self.clone()))
+    }
+    fn apply(&self, args: Vec<ty::Ty>) -> Result<ty::Ty, TypeError> {
+        let sig = self.signature();
+        // `Concat` は (長さ, 音) の組を並べるので，組の数だけシグネチャの引数を繰り返す
+        let sig = match self {
+            Func::Concat => ty::Func {
+                args: (0..args.len().div_ceil(2))
+                    .flat_map(|_| self.signature().args)
+                    .collect(),
+                ret: sig.ret,
+            },
+            _ => sig,
+        };
+        match sig.eval(&args) {
+            Ok(ty) if matches!(self, Func::Trim | Func::Concat) => trimmable(ty),
+            Ok(ty) if matches!(self, Func::Adsr) => {
+                gate(args[4].as_sound().unwrap())?;
+                Ok(ty)
+            }
+            Ok(ty) => Ok(ty),
+            Err(reason) => Err(TypeError::Builtin {
+                func: self.clone(),
+                args,
+                reason,
+            }),
+        }
+    }
+}
+
+impl Value {
+    pub fn ty(&self) -> Result<ty::Ty, TypeError> {
+        match self {
+            Value::Int(_) => Ok(ty::Ty::int()),
+            Value::Float(_) => Ok(ty::Ty::float()),
+            Value::Bool(_) => Ok(ty::Ty::bool()),
+            Value::Frame(frame) => Ok(ty::Ty::frame(frame.len())),
+            Value::Func(func) => func.ty(),
+            Value::Sound(sound) => Ok(ty::Ty::sound(sound.ty()?)),
+        }
+    }
+}
+
+impl Sound {
+    pub fn ty(&self) -> Result<ty::Ty, TypeError> {
+        match self {
+            Sound::T
+            | Sound::Integrate(_)
+            | Sound::Filter(..)
+            | Sound::Noise(..)
+            | Sound::Curve(_) => Ok(ty::Ty::float()),
+            Sound::Adsr(_, sound) => {
+                gate(&sound.ty()?)?;
+                Ok(ty::Ty::float())
+            }
+            Sound::Const(value) => value.ty(),
+            Sound::App(func, args) => func.apply(
+                args.iter()
+                    .map(|sound| sound.ty())
+                    .collect::<Result<_, _>>()?,
+            ),
+            Sound::Shift(_, sound) => sound.ty(),
+            Sound::Stretch(scale, sound) => {
+                if !(*scale > 0. && scale.is_finite()) {
+                    return Err(TypeError::Scale(*scale));
+                }
+                sound.ty()
+            }
+            Sound::Trim(from, to, sound) => {
+                if to < from {
+                    return Err(TypeError::Window(*from, *to));
+                }
+                trimmable(sound.ty()?)
+            }
+            Sound::Sample(sample) => match sample.channels() {
+                1 => Ok(ty::Ty::float()),
+                n => Ok(ty::Ty::frame(n)),
+            },
+            Sound::Merge(sounds) => {
+                for sound in sounds {
+                    let ty = sound.ty()?;
+                    if ty != ty::Ty::float() {
+                        return Err(TypeError::Channel(ty));
+                    }
+                }
+                Ok(ty::Ty::frame(sounds.len()))
+            }
+            Sound::Channel(i, sound) => {
+                let ty = sound.ty()?;
+                match ty.as_frame() {
+                    Some(n) if *i < n => Ok(ty::Ty::float()),
+                    Some(n) => Err(TypeError::ChannelIndex(*i, n)),
+                    None => Err(TypeError::Channel(ty)),
+                }
+            }
+            Sound::Flatten(sound) | Sound::Trigger(sound) => {
+                let ty = sound.ty()?;
+                match ty.as_sound() {
+                    Some(inner) => Ok(inner.clone()),
+                    None => Err(TypeError::Nested(ty)),
+                }
+            }
+            Sound::Concat(sounds) => {
+                let mut start = 0.;
+                for (len, _) in sounds {
+                    if *len < 0. {
+                        return Err(TypeError::Window(start, start + len));
+                    }
+                    start += len;
+                }
+                trimmable(combine(sounds)?)
+            }
+            Sound::Mix(sounds) => match combine(sounds)? {
+                ty if ty == ty::Ty::float() || ty.as_frame().is_some() => Ok(ty),
+                ty => Err(TypeError::Mix(ty)),
+            },
+        }
+    }
+}
+
+fn combine(sounds: &[(f64, Arc<Sound>)]) -> Result<ty::Ty, TypeError> {
+    let tys = sounds
+        .iter()
+        .map(|(_, sound)| sound.ty())
+        .collect::<Result<Vec<_>, _>>()?;
+    match tys.split_first() {
+        Some((first, rest)) if rest.iter().any(|ty| ty != first) => Err(TypeError::Concat(tys)),
+        Some((first, _)) => Ok(first.clone()),
+        None => Ok(ty::Ty::float()),
+    }
+}
+
+fn gate(ty: &ty::Ty) -> Result<(), TypeError> {
+    match ty.kind() {
+        ty::Kind::Bool | ty::Kind::Float => Ok(()),
+        _ => Err(TypeError::Gate(ty.clone())),
+    }
+}
+
+fn trimmable(ty: ty::Ty) -> Result<ty::Ty, TypeError> {
+    match silence(&ty) {
+        Some(_) => Ok(ty),
+        None => Err(TypeError::Silence(ty)),
+    }
+}
+
+/// 値が `ty` の音の無音．`Frame` の無音は全チャンネル 0 とみなす `Value::Float`．関数には無音がない．
+pub fn silence(ty: &ty::Ty) -> Option<Value> {
+    match ty.kind() {
+        ty::Kind::Int => Some(Value::Int(0)),
+        ty::Kind::Float | ty::Kind::Frame => Some(Value::Float(0.)),
+        ty::Kind::Bool => Some(Value::Bool(false)),
+        ty::Kind::Sound => {
+            let inner = silence(ty.as_sound()?)?;
+            Some(Value::Sound(Arc::new(Sound::Trim(
+                0.,
+                0.,
+                Arc::new(Sound::Const(Box::new(inner))),
+            ))))
+        }
+        ty::Kind::Func => None,
+    }
+}
+
+impl Expr {
+    pub fn check(&self, locals: &[ty::Ty]) -> Result<ty::Ty, TypeError> {
+        match *self {
+            Expr::Imm(ref value) => value.ty(),
+            Expr::Local(pos) => locals.get(pos).cloned().ok_or(TypeError::Local(pos)),
+            Expr::Call(ref func, ref args) => {
+                // 組み込み関数を直接 `App` するなら，多相でも各時刻の値の型に適用できる
+                if let (Expr::Imm(Value::Func(Func::App)), Some(Expr::Imm(Value::Func(inner)))) =
+                    (&**func, args.first())
+                {
+                    let sounds = args[1..]
+                        .iter()
+                        .map(|arg| arg.check(locals))
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if let Some(elems) = sounds
+                        .iter()
+                        .map(|ty| ty.as_sound().cloned())
+                        .collect::<Option<Vec<_>>>()
+                    {
+                        return Ok(ty::Ty::sound(inner.apply(elems)?));
+                    }
+                }
+                if let (
+                    Expr::Imm(Value::Func(Func::Trim)),
+                    [Expr::Imm(Value::Float(from)), Expr::Imm(Value::Float(to)), _],
+                ) = (&**func, &args[..])
+                {
+                    if to < from {
+                        return Err(TypeError::Window(*from, *to));
+                    }
+                }
+                if let (
+                    Expr::Imm(Value::Func(Func::Stretch)),
+                    [Expr::Imm(Value::Float(scale)), _],
+                ) = (&**func, &args[..])
+                {
+                    if !(*scale > 0. && scale.is_finite()) {
+                        return Err(TypeError::Scale(*scale));
+                    }
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| arg.check(locals))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if let Expr::Imm(Value::Func(ref func)) = **func {
+                    return func.apply(args);
+                }
+                let func = func.check(locals)?;
+                match func.as_func() {
+                    Some((ret, params)) if params == args => Ok(ret.clone()),
+                    Some(_) => Err(TypeError::Call { func, args }),
+                    None => Err(TypeError::NotFunc(func)),
+                }
+            }
+            Expr::Lambda(ref params, ref ret, ref body) => {
+                let locals = locals.iter().chain(params).cloned().collect::<Vec<_>>();
+                let found = body.check(&locals)?;
+                if found != *ret {
+                    return Err(TypeError::Return {
+                        expected: ret.clone(),
+                        found,
+                    });
+                }
+                Ok(ty::Ty::func(ret.clone(), params.clone()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imm(func: Func) -> Expr {
+        Expr::Imm(Value::Func(func))
+    }
+
+    fn call(func: Func, args: Vec<Expr>) -> Expr {
+        Expr::Call(Box::new(imm(func)), args)
+    }
+
+    fn float(x: f64) -> Expr {
+        Expr::Imm(Value::Float(x))
+    }
+
+    #[test]
+    fn check_exprs() {
+        let locals = [ty::Ty::sound(ty::Ty::float()), ty::Ty::float()];
+        let sound = ty::Ty::sound(ty::Ty::float());
+        let add = call(
+            Func::App,
+            vec![
+                imm(Func::Add),
+                Expr::Local(0),
+                call(Func::Const, vec![Expr::Local(1)]),
+            ],
+        );
+        assert_eq!(add.check(&locals).unwrap(), sound);
+        for expr in [
+            call(Func::App, vec![imm(Func::Add), Expr::Local(0)]),
+            call(Func::App, vec![imm(Func::Sin), Expr::Local(1)]),
+        ] {
+            assert!(matches!(
+                expr.check(&locals),
+                Err(TypeError::Builtin { .. })
+            ));
+        }
+        assert!(matches!(
+            Expr::Call(Box::new(Expr::Local(1)), vec![]).check(&locals),
+            Err(TypeError::NotFunc(_))
+        ));
+        assert!(matches!(
+            Expr::Local(2).check(&locals),
+            Err(TypeError::Local(2))
+        ));
+        assert!(matches!(
+            imm(Func::App).check(&locals),
+            Err(TypeError::Polymorphic(Func::App))
+        ));
+    }
+
+    #[test]
+    fn sound_builtins() {
+        let locals = [ty::Ty::sound(ty::Ty::float()), ty::Ty::float()];
+        let sound = ty::Ty::sound(ty::Ty::float());
+        for expr in [
+            call(Func::Integrate, vec![Expr::Local(0)]),
+            call(Func::Osc, vec![imm(Func::Saw), Expr::Local(0)]),
+            call(Func::OnePole, vec![Expr::Local(1), Expr::Local(0)]),
+            call(Func::Delay, vec![float(0.1), Expr::Local(0)]),
+            call(Func::Lowpass, vec![float(800.), float(0.7), Expr::Local(0)]),
+            call(Func::Comb, vec![float(0.01), float(0.5), Expr::Local(0)]),
+        ] {
+            assert_eq!(expr.check(&locals).unwrap(), sound);
+        }
+        for expr in [
+            call(Func::Integrate, vec![Expr::Local(1)]),
+            call(Func::Osc, vec![imm(Func::Add), Expr::Local(0)]),
+            call(Func::Delay, vec![Expr::Local(0), Expr::Local(0)]),
+            call(Func::Highpass, vec![float(800.), Expr::Local(0)]),
+        ] {
+            assert!(matches!(
+                expr.check(&locals),
+                Err(TypeError::Builtin { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn frames() {
+        let locals = [ty::Ty::sound(ty::Ty::float())];
+        let pan = call(
+            Func::App,
+            vec![imm(Func::Pan), Expr::Local(0), Expr::Local(0)],
+        );
+        let mono = call(Func::App, vec![imm(Func::MixDown), pan]);
+        assert_eq!(mono.check(&locals).unwrap(), locals[0]);
+
+        let t = Arc::new(Sound::T);
+        let stereo = Arc::new(Sound::Merge(vec![t.clone(), t.clone()]));
+        assert_eq!(stereo.ty().unwrap(), ty::Ty::frame(2));
+        assert_eq!(
+            Sound::Channel(1, stereo.clone()).ty().unwrap(),
+            ty::Ty::float()
+        );
+        assert!(matches!(
+            Sound::Channel(2, stereo.clone()).ty(),
+            Err(TypeError::ChannelIndex(2, 2))
+        ));
+        assert!(matches!(
+            Sound::Channel(0, t.clone()).ty(),
+            Err(TypeError::Channel(_))
+        ));
+        assert!(matches!(
+            Sound::Merge(vec![stereo.clone()]).ty(),
+            Err(TypeError::Channel(_))
+        ));
+
+        // チャンネル数の違うフレームは足せない
+        let surround = Arc::new(Sound::Merge(vec![t.clone(), t.clone(), t]));
+        let add = Sound::App(Func::AddFrame, vec![stereo.clone(), surround.clone()]);
+        assert!(matches!(add.ty(), Err(TypeError::Builtin { .. })));
+        let add = Sound::App(Func::AddFrame, vec![surround.clone(), surround.clone()]);
+        assert_eq!(add.ty().unwrap(), ty::Ty::frame(3));
+        let mix = Sound::Mix(vec![(0., stereo), (1., surround)]);
+        assert!(matches!(mix.ty(), Err(TypeError::Concat(_))));
+    }
+
+    #[test]
+    fn nested_sounds() {
+        let locals = [ty::Ty::sound(ty::Ty::float())];
+        let nested = || call(Func::Const, vec![Expr::Local(0)]);
+        for func in [Func::Flatten, Func::Trigger] {
+            let flat = call(func.clone(), vec![nested()]);
+            assert_eq!(flat.check(&locals).unwrap(), locals[0]);
+            let flat = call(func, vec![Expr::Local(0)]);
+            assert!(matches!(
+                flat.check(&locals),
+                Err(TypeError::Builtin { .. })
+            ));
+        }
+        assert!(matches!(
+            Sound::Flatten(Arc::new(Sound::T)).ty(),
+            Err(TypeError::Nested(_))
+        ));
+    }
+
+    #[test]
+    fn if_unifies_branches() {
+        let locals = [ty::Ty::bool(), ty::Ty::int(), ty::Ty::float()];
+        let branch = |then, otherwise| {
+            call(
+                Func::If,
+                vec![Expr::Local(0), Expr::Local(then), Expr::Local(otherwise)],
+            )
+        };
+        assert_eq!(branch(1, 1).check(&locals).unwrap(), ty::Ty::int());
+        assert!(matches!(
+            branch(1, 2).check(&locals),
+            Err(TypeError::Builtin { .. })
+        ));
+        let less = call(Func::Less, vec![Expr::Local(2), Expr::Local(2)]);
+        assert_eq!(less.check(&locals).unwrap(), ty::Ty::bool());
+    }
+
+    #[test]
+    fn concat_pairs() {
+        let locals = [ty::Ty::sound(ty::Ty::float())];
+        let nested = || call(Func::Const, vec![Expr::Local(0)]);
+        let concat = |args| call(Func::Concat, args).check(&locals);
+        assert_eq!(
+            concat(vec![float(1.), Expr::Local(0), float(2.), Expr::Local(0)]).unwrap(),
+            locals[0]
+        );
+        assert_eq!(
+            concat(vec![float(1.), nested()]).unwrap(),
+            ty::Ty::sound(locals[0].clone())
+        );
+        for args in [
+            vec![],
+            vec![float(1.), Expr::Local(0), float(1.), nested()],
+            vec![float(1.), Expr::Local(0), float(1.)],
+            vec![Expr::Local(0), float(1.)],
+        ] {
+            assert!(matches!(concat(args), Err(TypeError::Builtin { .. })));
+        }
+
+        let mixed = concat(vec![float(1.), Expr::Local(0), float(1.), nested()]);
+        match mixed {
+            Err(TypeError::Builtin { reason, .. }) => {
+                assert!(matches!(reason, ty::Mismatch::Arg(3, _)), "{reason:?}")
+            }
+            other => panic!("{other:?}"),
+        }
+
+        let mixed = Sound::Concat(vec![
+            (1., Arc::new(Sound::T)),
+            (
+                1.,
+                Arc::new(Sound::Const(Box::new(Value::Sound(Arc::new(Sound::T))))),
+            ),
+        ]);
+        assert!(matches!(mixed.ty(), Err(TypeError::Concat(_))));
+    }
+
+    fn check(input: &str) -> Result<Vec<ty::Ty>, TypeError> {
+        parse::parse(input, &["t"])
+            .unwrap()
+            .check(vec![ty::Ty::sound(ty::Ty::float())])
+    }
+
+    #[test]
+    fn trim_needs_silence() {
+        assert!(check("out = trim(0.0, 1.0, app(less, t, const(0.5)))").is_ok());
+        assert!(check("out = trim(0.0, 1.0, const(const(1)))").is_ok());
+        assert!(matches!(
+            check("out = trim(0.0, 1.0, const(sin))"),
+            Err(TypeError::Silence(_))
+        ));
+        assert!(matches!(
+            check("out = concat(1.0, const(sin))"),
+            Err(TypeError::Silence(_))
+        ));
+    }
+
+    #[test]
+    fn reversed_window() {
+        assert!(matches!(
+            check("out = trim(0.5, 0.2, t)"),
+            Err(TypeError::Window(from, to)) if from == 0.5 && to == 0.2
+        ));
+        let sound = Sound::Concat(vec![(1., Arc::new(Sound::T)), (-0.5, Arc::new(Sound::T))]);
+        assert!(matches!(sound.ty(), Err(TypeError::Window(..))));
+    }
+
+    #[test]
+    fn stretch_scales() {
+        assert!(check("out = stretch(2.0, t)").is_ok());
+        for input in ["out = stretch(0.0, t)", "out = stretch(-1.0, t)"] {
+            assert!(matches!(check(input), Err(TypeError::Scale(_))));
+        }
+        let sound = Sound::Stretch(f64::NAN, Arc::new(Sound::T));
+        assert!(matches!(sound.ty(), Err(TypeError::Scale(_))));
+    }
+
+    #[test]
+    fn adsr_gates() {
+        assert!(check("out = adsr(0.1, 0.1, 0.5, 0.2, app(less, t, const(0.5)))").is_ok());
+        assert!(check("out = adsr(0.1, 0.1, 0.5, 0.2, t)").is_ok());
+        assert!(matches!(
+            check("out = adsr(0.1, 0.1, 0.5, 0.2, const(1))"),
+            Err(TypeError::Gate(_))
+        ));
+        let gate = Arc::new(Sound::Const(Box::new(Value::Int(1))));
+        let adsr = envelope::Adsr {
+            attack: 0.1,
+            decay: 0.1,
+            sustain: 0.5,
+            release: 0.2,
+        };
+        assert!(matches!(
+            Sound::Adsr(adsr, gate).ty(),
+            Err(TypeError::Gate(_))
+        ));
+    }
+
+    #[test]
+    fn mix_needs_numbers() {
+        let bools = Arc::new(Sound::Const(Box::new(Value::Bool(true))));
+        let sound = Sound::Mix(vec![(0., bools.clone()), (1., bools)]);
+        assert!(matches!(sound.ty(), Err(TypeError::Mix(_))));
+    }
+}
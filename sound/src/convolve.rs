@@ -0,0 +1,176 @@
+use std::{collections::VecDeque, fmt};
+
+use crate::{
+    fft::{fft, Complex},
+    sample::Sample,
+};
+
+/// 区切りの長さ．これより短い部分は直接畳み込む．
+const PARTITION: usize = 256;
+
+pub struct Impulse {
+    rate: f64,
+    data: Vec<f64>,
+}
+
+impl fmt::Debug for Impulse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Impulse")
+            .field("rate", &self.rate)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+impl Impulse {
+    pub fn new(rate: f64, data: Vec<f64>) -> Impulse {
+        Impulse { rate, data }
+    }
+    pub fn from_sample(sample: &Sample, channel: usize) -> Option<Impulse> {
+        Some(Impulse::new(sample.rate(), sample.channel(channel)?))
+    }
+    pub fn end(&self) -> f64 {
+        self.data.len() as f64 / self.rate
+    }
+    /// 周波数が違えば線形補間で変換し，全体の利得を保つ．
+    pub fn state(&self, rate: f64) -> State {
+        if rate == self.rate {
+            return State::new(&self.data);
+        }
+        let ratio = self.rate / rate;
+        let len = (self.data.len() as f64 / ratio).ceil() as usize;
+        let get = |i: usize| self.data.get(i).copied().unwrap_or(0.);
+        let taps: Vec<_> = (0..len)
+            .map(|i| {
+                let pos = i as f64 * ratio;
+                let (i, x) = (pos.floor() as usize, pos.fract());
+                (get(i) + x * (get(i + 1) - get(i))) * ratio
+            })
+            .collect();
+        State::new(&taps)
+    }
+}
+
+/// 一様に区切った畳み込み．先頭の区切りは遅延なしに直接計算し，
+/// 残りは区切りごとの入力のスペクトルと掛け合わせて重ね合わせる．
+pub struct State {
+    head: Vec<f64>,
+    partitions: Vec<Vec<Complex>>,
+    /// 入力の区切りのスペクトル．新しいものが先頭．
+    spectra: VecDeque<Vec<Complex>>,
+    input: Vec<f64>,
+    pos: usize,
+    tail: Vec<f64>,
+    overlap: Vec<f64>,
+    buf: Vec<Complex>,
+}
+
+impl State {
+    fn new(taps: &[f64]) -> State {
+        let head = taps[..taps.len().min(PARTITION)].to_vec();
+        let partitions: Vec<_> = taps
+            .chunks(PARTITION)
+            .skip(1)
+            .map(|chunk| {
+                let mut spectrum = vec![Complex::default(); 2 * PARTITION];
+                for (slot, &x) in spectrum.iter_mut().zip(chunk) {
+                    slot.re = x;
+                }
+                fft(&mut spectrum, false);
+                spectrum
+            })
+            .collect();
+        State {
+            head,
+            spectra: VecDeque::with_capacity(partitions.len() + 1),
+            partitions,
+            input: vec![0.; 2 * PARTITION],
+            pos: 0,
+            tail: vec![0.; PARTITION],
+            overlap: vec![0.; PARTITION],
+            buf: vec![Complex::default(); 2 * PARTITION],
+        }
+    }
+    pub fn process(&mut self, input: f64) -> f64 {
+        let pos = PARTITION + self.pos;
+        self.input[pos] = input;
+        let mut output = self.tail[self.pos];
+        for (i, h) in self.head.iter().enumerate() {
+            output += h * self.input[pos - i];
+        }
+        self.pos += 1;
+        if self.pos == PARTITION {
+            self.pos = 0;
+            self.flush();
+        }
+        output
+    }
+    fn flush(&mut self) {
+        let (prev, current) = self.input.split_at_mut(PARTITION);
+        prev.copy_from_slice(current);
+        if self.partitions.is_empty() {
+            return;
+        }
+        // 使わなくなったスペクトルの領域を使い回す
+        let mut spectrum = match self.spectra.len() > self.partitions.len() {
+            true => self.spectra.pop_back().unwrap(),
+            false => Vec::with_capacity(2 * PARTITION),
+        };
+        spectrum.clear();
+        spectrum.extend(prev.iter().map(|&x| Complex::new(x, 0.)));
+        spectrum.resize(2 * PARTITION, Complex::default());
+        fft(&mut spectrum, false);
+        self.spectra.push_front(spectrum);
+
+        self.buf.fill(Complex::default());
+        for (x, h) in self.spectra.iter().zip(&self.partitions) {
+            for (slot, (&x, &h)) in self.buf.iter_mut().zip(x.iter().zip(h)) {
+                *slot = *slot + x * h;
+            }
+        }
+        fft(&mut self.buf, true);
+        for i in 0..PARTITION {
+            self.tail[i] = self.buf[i].re + self.overlap[i];
+            self.overlap[i] = self.buf[PARTITION + i].re;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noise(seed: u64, n: usize) -> Vec<f64> {
+        let mut state = crate::noise::Color::White.state(seed);
+        (0..n).map(|_| state.sample()).collect()
+    }
+
+    #[test]
+    fn matches_direct_convolution() {
+        // 区切りをまたぐ長さにする
+        let taps: Vec<_> = noise(3, 1500).into_iter().map(|x| x * 0.05).collect();
+        let input = noise(4, 4000);
+        let mut state = Impulse::new(44100., taps.clone()).state(44100.);
+        let output: Vec<_> = input.iter().map(|&x| state.process(x)).collect();
+        for (n, y) in output.into_iter().enumerate() {
+            let direct: f64 = (0..=n.min(taps.len() - 1))
+                .map(|k| taps[k] * input[n - k])
+                .sum();
+            assert!((direct - y).abs() < 1e-9, "#{n}: {direct} {y}");
+        }
+    }
+
+    #[test]
+    fn from_sample() {
+        let sample = Sample::new(22050., 2, [0.01, 0.].repeat(100));
+        assert!(Impulse::from_sample(&sample, 2).is_none());
+        let impulse = Impulse::from_sample(&sample, 0).unwrap();
+        assert_eq!(impulse.end(), 100. / 22050.);
+        // 周波数を変えても全体の利得はほぼ変わらない
+        let mut state = impulse.state(44100.);
+        let sum: f64 = (0..256)
+            .map(|i| state.process(if i == 0 { 1. } else { 0. }))
+            .sum();
+        assert!((sum - 1.).abs() < 0.01, "{sum}");
+    }
+}
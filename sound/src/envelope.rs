@@ -0,0 +1,176 @@
+/// ゲートが開くと 0 から 1 まで `attack` 秒で上がり，`decay` 秒で `sustain` まで下がってそのまま保つ．
+/// ゲートが閉じるとその時点の値から `release` 秒で 0 まで下がる．各区間は直線で変化する．
+#[derive(Clone, Copy, Debug)]
+pub struct Adsr {
+    pub attack: f64,
+    pub decay: f64,
+    pub sustain: f64,
+    pub release: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release(f64),
+}
+
+impl Adsr {
+    pub fn state(self) -> State {
+        State {
+            adsr: self,
+            stage: Stage::Idle,
+            level: 0.,
+            gate: false,
+            prev: None,
+        }
+    }
+}
+
+pub struct State {
+    adsr: Adsr,
+    stage: Stage,
+    level: f64,
+    gate: bool,
+    prev: Option<f64>,
+}
+
+impl State {
+    pub fn process(&mut self, t: f64, gate: bool) -> f64 {
+        let Adsr {
+            attack,
+            decay,
+            sustain,
+            release,
+        } = self.adsr;
+        let dt = self.prev.map_or(0., |prev| t - prev);
+        self.prev = Some(t);
+        match (self.gate, gate) {
+            (false, true) => self.stage = Stage::Attack,
+            (true, false) => self.stage = Stage::Release(self.level),
+            _ => {}
+        }
+        self.gate = gate;
+        // 区間の長さが 0 以下なら直ちに次の区間に移る
+        let step = |length: f64| {
+            if length > 0. {
+                dt / length
+            } else {
+                f64::INFINITY
+            }
+        };
+        match self.stage {
+            Stage::Idle => self.level = 0.,
+            Stage::Attack => {
+                self.level += step(attack);
+                if self.level >= 1. {
+                    self.level = 1.;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= step(decay) * (1. - sustain);
+                if self.level <= sustain || decay <= 0. {
+                    self.level = sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = sustain,
+            Stage::Release(from) => {
+                self.level -= step(release) * from;
+                if self.level <= 0. || release <= 0. {
+                    self.level = 0.;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    Linear,
+    /// 値の比が一定の割合で変わる．両端の符号が違うか 0 を含む区間は直線にする．
+    Exponential,
+}
+
+/// (時刻 [s], 値) の点を結んだ曲線．最初の点より前は最初の値，最後の点より後は最後の値を保つ．
+#[derive(Clone, Debug)]
+pub struct Curve {
+    points: Vec<(f64, f64)>,
+    shape: Shape,
+}
+
+impl Curve {
+    pub fn new(mut points: Vec<(f64, f64)>, shape: Shape) -> Curve {
+        points.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Curve { points, shape }
+    }
+    pub fn points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+    pub fn at(&self, t: f64) -> f64 {
+        let i = self.points.partition_point(|&(time, _)| time <= t);
+        let (t0, v0) = match i {
+            0 => return self.points.first().map_or(0., |&(_, value)| value),
+            i => self.points[i - 1],
+        };
+        let Some(&(t1, v1)) = self.points.get(i) else {
+            return v0;
+        };
+        let x = (t - t0) / (t1 - t0);
+        match self.shape {
+            Shape::Exponential if v0 * v1 > 0. => v0 * (v1 / v0).powf(x),
+            Shape::Linear | Shape::Exponential => v0 + x * (v1 - v0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adsr_stages() {
+        let adsr = Adsr {
+            attack: 0.1,
+            decay: 0.1,
+            sustain: 0.5,
+            release: 0.2,
+        };
+        let mut state = adsr.state();
+        let values: Vec<_> = (0..20)
+            .map(|i| {
+                let t = i as f64 / 20.;
+                state.process(t, t < 0.5)
+            })
+            .collect();
+        let expected = [
+            0., 0.5, 1., 0.75, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.375, 0.25, 0.125, 0., 0., 0., 0.,
+            0., 0., 0.,
+        ];
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{values:?}");
+        }
+    }
+
+    #[test]
+    fn curve_shapes() {
+        let points = vec![(0.5, 0.01), (0., 1.), (1., 0.)];
+        let linear = Curve::new(points.clone(), Shape::Linear);
+        assert_eq!(linear.points()[0], (0., 1.));
+        assert_eq!(linear.at(-1.), 1.);
+        assert!((linear.at(0.25) - 0.505).abs() < 1e-9);
+        assert_eq!(linear.at(2.), 0.);
+        let exponential = Curve::new(points, Shape::Exponential);
+        assert!((exponential.at(0.25) - 0.1).abs() < 1e-9);
+        // 0 を含む区間は直線になる
+        assert!((exponential.at(0.75) - 0.005).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,93 @@
+use std::fmt;
+
+use super::*;
+
+#[derive(Debug)]
+pub enum Error {
+    Type {
+        func: Func,
+        pos: usize,
+        expected: ty::Kind,
+        found: Value,
+    },
+    /// `func` に渡された引数の個数が違う．`max` が `None` なら可変長．
+    Arity {
+        func: Func,
+        min: usize,
+        max: Option<usize>,
+        found: usize,
+    },
+    DivZero(Func),
+    NotFunc(Value),
+    Local(usize),
+    Input {
+        node: &'static str,
+        expected: ty::Kind,
+        found: Value,
+    },
+    NotFloat(usize, Value),
+    Channels(usize, usize),
+    /// `n` チャンネルのフレームから `i` 番目のチャンネルを取り出そうとした．
+    Channel(usize, usize),
+    /// 区間 [from, to) の終わりが始まりより前にある．
+    Window(f64, f64),
+    /// 時間の伸縮率が正の有限な数でない．
+    Scale(f64),
+    NoChannels,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Type {
+                func,
+                pos,
+                expected,
+                found,
+            } => write!(
+                f,
+                "argument #{pos} of {func:?}: expected {expected:?}, found {:?} ({found:?})",
+                found.kind()
+            ),
+            Error::Arity {
+                func,
+                min,
+                max,
+                found,
+            } => match max {
+                Some(max) if max == min => {
+                    write!(f, "{func:?} takes {min} argument(s), but {found} given")
+                }
+                Some(max) => write!(
+                    f,
+                    "{func:?} takes {min} to {max} arguments, but {found} given"
+                ),
+                None => write!(
+                    f,
+                    "{func:?} takes at least {min} argument(s), but {found} given"
+                ),
+            },
+            Error::DivZero(func) => write!(f, "{func:?}: division by zero"),
+            Error::NotFunc(value) => write!(f, "{value:?} is not a function"),
+            Error::Local(pos) => write!(f, "local #{pos} is not defined"),
+            Error::Input {
+                node,
+                expected,
+                found,
+            } => write!(
+                f,
+                "input of {node}: expected {expected:?}, found {:?} ({found:?})",
+                found.kind()
+            ),
+            Error::NotFloat(pos, value) => write!(f, "sample #{pos} is not a float: {value:?}"),
+            Error::Channels(expected, found) => {
+                write!(f, "expected {expected} channel(s), found {found}")
+            }
+            Error::Channel(i, n) => write!(f, "channel #{i} of a frame with {n} channel(s)"),
+            Error::Window(from, to) => write!(f, "window [{from}, {to}) ends before it starts"),
+            Error::Scale(scale) => write!(f, "cannot stretch time by {scale}"),
+            Error::NoChannels => write!(f, "at least one channel is required"),
+        }
+    }
+}
+impl std::error::Error for Error {}
@@ -0,0 +1,113 @@
+use std::{
+    f64::consts::TAU,
+    ops::{Add, Mul, Sub},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Complex {
+        Complex { re, im }
+    }
+    pub fn norm(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+}
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// 基数 2 の高速フーリエ変換をその場で行う．長さは 2 のべきでなければならない．
+/// `inverse` なら逆変換し，長さで割る．
+pub fn fft(data: &mut [Complex], inverse: bool) {
+    let n = data.len();
+    assert!(n.is_power_of_two(), "FFT size {n} is not a power of two");
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1. } else { -1. };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * TAU / len as f64;
+        let step = Complex::new(angle.cos(), angle.sin());
+        for chunk in data.chunks_mut(len) {
+            let (lower, upper) = chunk.split_at_mut(len / 2);
+            let mut w = Complex::new(1., 0.);
+            for (a, b) in lower.iter_mut().zip(upper) {
+                let t = *b * w;
+                *b = *a - t;
+                *a = *a + t;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        for x in data {
+            x.re /= n as f64;
+            x.im /= n as f64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impulse_is_flat() {
+        let mut data = vec![Complex::default(); 8];
+        data[0] = Complex::new(1., 0.);
+        fft(&mut data, false);
+        assert!(data.iter().all(|x| (*x - Complex::new(1., 0.)).norm() < 1e-12));
+    }
+
+    #[test]
+    fn cosine_and_inverse() {
+        let n = 16;
+        let input: Vec<_> = (0..n)
+            .map(|i| Complex::new((TAU * 3. * i as f64 / n as f64).cos(), 0.))
+            .collect();
+        let mut data = input.clone();
+        fft(&mut data, false);
+        for (k, x) in data.iter().enumerate() {
+            let expected = if k == 3 || k == n - 3 { n as f64 / 2. } else { 0. };
+            assert!((x.norm() - expected).abs() < 1e-9, "bin {k}: {x:?}");
+        }
+        fft(&mut data, true);
+        for (x, y) in data.iter().zip(&input) {
+            assert!((*x - *y).norm() < 1e-12);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::{f64::consts::TAU, sync::Arc};
+
+use crate::convolve;
+
+/// 評価開始前の入出力はすべて 0 とみなす．
+#[derive(Clone, Debug)]
+pub enum Filter {
+    OnePole(f64),
+    Biquad(BiquadKind, f64, f64),
+    /// 遅延 [s]．
+    Delay(f64),
+    /// 遅延 [s] とフィードバック量．
+    Comb(f64, f64),
+    Convolve(Arc<convolve::Impulse>),
+    /// 残響時間 [s]，高域の減衰 [0, 1]，残響の量．原音に残響を足す．
+    Reverb(f64, f64, f64),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum BiquadKind {
+    Lowpass,
+    Highpass,
+    Bandpass,
+}
+
+impl Filter {
+    pub fn state(&self, rate: f64) -> State {
+        match *self {
+            Filter::OnePole(cutoff) => State::OnePole {
+                coef: 1. - (-TAU * cutoff / rate).exp(),
+                prev: 0.,
+            },
+            Filter::Biquad(kind, freq, q) => {
+                let omega = TAU * freq / rate;
+                let alpha = omega.sin() / (2. * q);
+                let cos = omega.cos();
+                let b = match kind {
+                    BiquadKind::Lowpass => [(1. - cos) / 2., 1. - cos, (1. - cos) / 2.],
+                    BiquadKind::Highpass => [(1. + cos) / 2., -(1. + cos), (1. + cos) / 2.],
+                    BiquadKind::Bandpass => [alpha, 0., -alpha],
+                };
+                let a = [1. + alpha, -2. * cos, 1. - alpha];
+                State::Biquad {
+                    b: b.map(|b| b / a[0]),
+                    a: [a[1] / a[0], a[2] / a[0]],
+                    x: [0.; 2],
+                    y: [0.; 2],
+                }
+            }
+            Filter::Delay(delay) => State::Delay(DelayLine::new((delay * rate).round() as usize)),
+            Filter::Comb(delay, feedback) => {
+                State::Comb(DelayLine::new((delay * rate).round() as usize), feedback)
+            }
+            Filter::Convolve(ref impulse) => State::Convolve(Box::new(impulse.state(rate))),
+            Filter::Reverb(time, damping, wet) => {
+                State::Reverb(Box::new(Reverb::new(rate, time, damping, wet)))
+            }
+        }
+    }
+}
+
+pub enum State {
+    OnePole {
+        coef: f64,
+        prev: f64,
+    },
+    Biquad {
+        b: [f64; 3],
+        a: [f64; 2],
+        x: [f64; 2],
+        y: [f64; 2],
+    },
+    Delay(DelayLine),
+    Comb(DelayLine, f64),
+    Convolve(Box<convolve::State>),
+    Reverb(Box<Reverb>),
+}
+impl State {
+    pub fn process(&mut self, input: f64) -> f64 {
+        match self {
+            State::OnePole { coef, prev } => {
+                *prev += *coef * (input - *prev);
+                *prev
+            }
+            State::Biquad { b, a, x, y } => {
+                let output = b[0] * input + b[1] * x[0] + b[2] * x[1] - a[0] * y[0] - a[1] * y[1];
+                *x = [input, x[0]];
+                *y = [output, y[0]];
+                output
+            }
+            State::Delay(line) => line.push(input),
+            State::Comb(line, feedback) => {
+                let output = input + *feedback * line.peek();
+                line.push(output);
+                output
+            }
+            State::Convolve(state) => state.process(input),
+            State::Reverb(reverb) => reverb.process(input),
+        }
+    }
+}
+
+/// Freeverb と同じ構成の残響．減衰させた櫛形フィルタを並べて足し，全域通過フィルタを直列に通す．
+pub struct Reverb {
+    /// (遅延, フィードバック量, 減衰させた直前の出力)
+    combs: Vec<(DelayLine, f64, f64)>,
+    allpasses: Vec<DelayLine>,
+    damping: f64,
+    wet: f64,
+}
+impl Reverb {
+    /// 44100 Hz での遅延のサンプル数．
+    const COMBS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+    const ALLPASSES: [usize; 4] = [556, 441, 341, 225];
+
+    fn new(rate: f64, time: f64, damping: f64, wet: f64) -> Reverb {
+        let len = |len: usize| ((len as f64 * rate / 44100.).round() as usize).max(1);
+        Reverb {
+            combs: Reverb::COMBS
+                .iter()
+                .map(|&n| {
+                    let n = len(n);
+                    // 残響時間で 60 dB 減衰するフィードバック量
+                    let feedback = 0.001f64.powf(n as f64 / (rate * time.max(f64::MIN_POSITIVE)));
+                    (DelayLine::new(n), feedback, 0.)
+                })
+                .collect(),
+            allpasses: Reverb::ALLPASSES
+                .iter()
+                .map(|&n| DelayLine::new(len(n)))
+                .collect(),
+            damping: damping.clamp(0., 1.),
+            wet,
+        }
+    }
+    fn process(&mut self, input: f64) -> f64 {
+        let mut output = 0.;
+        for (line, feedback, store) in &mut self.combs {
+            let delayed = line.peek();
+            *store = delayed * (1. - self.damping) + *store * self.damping;
+            line.push(input * 0.015 + *store * *feedback);
+            output += delayed;
+        }
+        for line in &mut self.allpasses {
+            let delayed = line.peek();
+            line.push(output + delayed * 0.5);
+            output = delayed - output;
+        }
+        input + self.wet * 3. * output
+    }
+}
+
+pub struct DelayLine {
+    buf: Vec<f64>,
+    pos: usize,
+}
+impl DelayLine {
+    pub fn new(len: usize) -> DelayLine {
+        DelayLine {
+            buf: vec![0.; len],
+            pos: 0,
+        }
+    }
+    pub fn peek(&self) -> f64 {
+        self.buf.get(self.pos).copied().unwrap_or(0.)
+    }
+    pub fn push(&mut self, input: f64) -> f64 {
+        match self.buf.get_mut(self.pos) {
+            Some(slot) => {
+                let output = std::mem::replace(slot, input);
+                self.pos = (self.pos + 1) % self.buf.len();
+                output
+            }
+            None => input,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: f64 = 44100.;
+
+    /// `freq` Hz の正弦波を通し，落ち着いてからの振幅を返す．
+    fn gain(filter: &Filter, freq: f64) -> f64 {
+        let mut state = filter.state(RATE);
+        (0..8820)
+            .map(|i| state.process((TAU * freq * i as f64 / RATE).sin()))
+            .skip(4410)
+            .fold(0., |max: f64, x| max.max(x.abs()))
+    }
+
+    fn impulse(filter: &Filter, n: usize) -> Vec<f64> {
+        let mut state = filter.state(RATE);
+        (0..n)
+            .map(|i| state.process(if i == 0 { 1. } else { 0. }))
+            .collect()
+    }
+
+    #[test]
+    fn biquads() {
+        let lowpass = Filter::Biquad(BiquadKind::Lowpass, 800., 0.707);
+        assert!((gain(&lowpass, 100.) - 1.).abs() < 0.01);
+        assert!(gain(&lowpass, 8000.) < 0.02);
+        let highpass = Filter::Biquad(BiquadKind::Highpass, 2000., 0.707);
+        assert!(gain(&highpass, 100.) < 0.01);
+        assert!((gain(&highpass, 15000.) - 1.).abs() < 0.01);
+        let bandpass = Filter::Biquad(BiquadKind::Bandpass, 1000., 2.);
+        assert!((gain(&bandpass, 1000.) - 1.).abs() < 0.01);
+        assert!(gain(&bandpass, 100.) < 0.1);
+        let onepole = Filter::OnePole(500.);
+        assert!(gain(&onepole, 50.) > 0.99);
+        assert!(gain(&onepole, 10000.) < 0.1);
+    }
+
+    #[test]
+    fn delays() {
+        let delayed = impulse(&Filter::Delay(0.001), 100);
+        let at = (0.001 * RATE).round() as usize;
+        assert!(delayed
+            .iter()
+            .enumerate()
+            .all(|(i, &x)| x == (i == at) as u8 as f64));
+        let comb = impulse(&Filter::Comb(0.001, 0.5), 200);
+        assert_eq!(
+            [comb[0], comb[at], comb[2 * at], comb[3 * at]],
+            [1., 0.5, 0.25, 0.125]
+        );
+        // 長さ 0 の遅延はそのまま通す
+        assert_eq!(impulse(&Filter::Delay(0.), 3), [1., 0., 0.]);
+    }
+
+    #[test]
+    fn reverb_decays() {
+        let tail = impulse(&Filter::Reverb(1., 0.3, 1.), 88200);
+        let rms = |samples: &[f64]| {
+            (samples.iter().map(|x| x * x).sum::<f64>() / samples.len() as f64).sqrt()
+        };
+        let early = rms(&tail[..22050]);
+        let late = rms(&tail[66150..]);
+        assert!(early > 0.);
+        assert!(late < early * 0.01, "{early} {late}");
+    }
+}
@@ -0,0 +1,838 @@
+pub mod analysis;
+pub mod check;
+pub mod convolve;
+pub mod envelope;
+pub mod error;
+pub mod fft;
+pub mod filter;
+pub mod master;
+pub mod midi;
+pub mod noise;
+pub mod parallel;
+pub mod parse;
+pub mod sample;
+pub mod score;
+pub mod serial;
+pub mod sink;
+pub mod stream;
+pub mod ty;
+pub mod wav;
+
+use std::{f64::consts::TAU, sync::Arc};
+
+use enum_as_inner::EnumAsInner;
+
+use error::Error;
+
+#[derive(Clone, Debug, EnumAsInner)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Frame(Arc<[f64]>),
+    Func(Func),
+    Sound(Arc<Sound>),
+}
+impl Value {
+    pub fn kind(&self) -> ty::Kind {
+        match self {
+            Value::Int(_) => ty::Kind::Int,
+            Value::Float(_) => ty::Kind::Float,
+            Value::Bool(_) => ty::Kind::Bool,
+            Value::Frame(_) => ty::Kind::Frame,
+            Value::Func(_) => ty::Kind::Func,
+            Value::Sound(_) => ty::Kind::Sound,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Func {
+    Sin,
+    Saw,
+    Square,
+    Triangle,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
+    RemInt,
+    ToFloat,
+    Floor,
+    Less,
+    LessEq,
+    Equal,
+    LessInt,
+    LessEqInt,
+    EqualInt,
+    And,
+    Or,
+    Not,
+    If,
+    /// モノラルの値を [-1, 1] の位置に等パワーで定位させ，ステレオにする．
+    Pan,
+    MixDown,
+    AddFrame,
+    App,
+    Const,
+    Integrate,
+    Osc,
+    OnePole,
+    Lowpass,
+    Highpass,
+    Bandpass,
+    Delay,
+    Comb,
+    Concat,
+    Flatten,
+    Trigger,
+    Shift,
+    Stretch,
+    Trim,
+    Adsr,
+    Noise(noise::Color),
+    Closure(Arc<Closure>),
+}
+impl Func {
+    fn arity(&self) -> (usize, Option<usize>) {
+        match self {
+            Func::Sin
+            | Func::Saw
+            | Func::Square
+            | Func::Triangle
+            | Func::MixDown
+            | Func::Const
+            | Func::Flatten
+            | Func::Trigger
+            | Func::ToFloat
+            | Func::Floor
+            | Func::Not
+            | Func::Integrate
+            | Func::Noise(_) => (1, Some(1)),
+            Func::Add
+            | Func::Sub
+            | Func::Mul
+            | Func::Div
+            | Func::AddInt
+            | Func::SubInt
+            | Func::MulInt
+            | Func::DivInt
+            | Func::RemInt
+            | Func::Less
+            | Func::LessEq
+            | Func::Equal
+            | Func::LessInt
+            | Func::LessEqInt
+            | Func::EqualInt
+            | Func::And
+            | Func::Or
+            | Func::Pan
+            | Func::AddFrame
+            | Func::Osc
+            | Func::OnePole
+            | Func::Delay
+            | Func::Shift
+            | Func::Stretch => (2, Some(2)),
+            Func::If
+            | Func::Trim
+            | Func::Lowpass
+            | Func::Highpass
+            | Func::Bandpass
+            | Func::Comb => (3, Some(3)),
+            Func::Adsr => (5, Some(5)),
+            Func::Closure(closure) => (closure.params.len(), Some(closure.params.len())),
+            Func::App => (1, None),
+            Func::Concat => (0, None),
+        }
+    }
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, Error> {
+        let (min, max) = self.arity();
+        if args.len() < min || max.is_some_and(|max| args.len() > max) {
+            return Err(Error::Arity {
+                func: self.clone(),
+                min,
+                max,
+                found: args.len(),
+            });
+        }
+        let mut args = Args {
+            func: self,
+            args: args.into_iter().enumerate(),
+        };
+        Ok(match self {
+            Func::Sin => {
+                let theta = args.float()?;
+                Value::Float(theta.sin())
+            }
+            Func::Saw => {
+                let phase = (args.float()? / TAU + 0.5).rem_euclid(1.);
+                Value::Float(2. * phase - 1.)
+            }
+            Func::Square => {
+                let phase = (args.float()? / TAU).rem_euclid(1.);
+                Value::Float(if phase < 0.5 { 1. } else { -1. })
+            }
+            Func::Triangle => {
+                let phase = (args.float()? / TAU + 0.25).rem_euclid(1.);
+                Value::Float(1. - 4. * (phase - 0.5).abs())
+            }
+            Func::Add => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Float(first + second)
+            }
+            Func::Sub => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Float(first - second)
+            }
+            Func::Mul => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Float(first * second)
+            }
+            Func::Div => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Float(first / second)
+            }
+            Func::AddInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Int(first.wrapping_add(second))
+            }
+            Func::SubInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Int(first.wrapping_sub(second))
+            }
+            Func::MulInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Int(first.wrapping_mul(second))
+            }
+            Func::DivInt | Func::RemInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                if second == 0 {
+                    return Err(Error::DivZero(self.clone()));
+                }
+                Value::Int(match self {
+                    Func::DivInt => first.wrapping_div(second),
+                    _ => first.wrapping_rem(second),
+                })
+            }
+            Func::ToFloat => Value::Float(args.int()? as f64),
+            Func::Floor => Value::Int(args.float()?.floor() as i64),
+            Func::Less => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Bool(first < second)
+            }
+            Func::LessEq => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Bool(first <= second)
+            }
+            Func::Equal => {
+                let first = args.float()?;
+                let second = args.float()?;
+                Value::Bool(first == second)
+            }
+            Func::LessInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Bool(first < second)
+            }
+            Func::LessEqInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Bool(first <= second)
+            }
+            Func::EqualInt => {
+                let first = args.int()?;
+                let second = args.int()?;
+                Value::Bool(first == second)
+            }
+            Func::And => {
+                let first = args.bool()?;
+                let second = args.bool()?;
+                Value::Bool(first && second)
+            }
+            Func::Or => {
+                let first = args.bool()?;
+                let second = args.bool()?;
+                Value::Bool(first || second)
+            }
+            Func::Not => Value::Bool(!args.bool()?),
+            Func::If => {
+                let cond = args.bool()?;
+                let (_, then) = args.next();
+                let (_, otherwise) = args.next();
+                if cond {
+                    then
+                } else {
+                    otherwise
+                }
+            }
+            Func::Pan => {
+                let value = args.float()?;
+                let theta = (args.float()?.clamp(-1., 1.) + 1.) * TAU / 8.;
+                Value::Frame(Arc::new([value * theta.cos(), value * theta.sin()]))
+            }
+            Func::MixDown => match args.frame()? {
+                Value::Frame(frame) => Value::Float(frame.iter().sum::<f64>() / frame.len() as f64),
+                value => value,
+            },
+            Func::AddFrame => {
+                let first = args.frame()?;
+                let second = args.frame()?;
+                stream::mix(&first, &second)?
+            }
+            Func::App => {
+                let func = args.func()?;
+                let sound = std::iter::from_fn(|| args.sound()).collect::<Result<_, _>>()?;
+                Value::Sound(Arc::new(Sound::App(func, sound)))
+            }
+            Func::Const => Value::Sound(Arc::new(Sound::Const(Box::new(args.next().1)))),
+            Func::Integrate => {
+                let sound = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::Integrate(sound)))
+            }
+            Func::Osc => {
+                let func = args.func()?;
+                let freq = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::osc(func, freq)))
+            }
+            Func::OnePole => {
+                let cutoff = args.float()?;
+                let sound = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::Filter(
+                    filter::Filter::OnePole(cutoff),
+                    sound,
+                )))
+            }
+            Func::Lowpass | Func::Highpass | Func::Bandpass => {
+                let kind = match self {
+                    Func::Lowpass => filter::BiquadKind::Lowpass,
+                    Func::Highpass => filter::BiquadKind::Highpass,
+                    _ => filter::BiquadKind::Bandpass,
+                };
+                let freq = args.float()?;
+                let q = args.float()?;
+                let sound = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::Filter(
+                    filter::Filter::Biquad(kind, freq, q),
+                    sound,
+                )))
+            }
+            Func::Delay => {
+                let delay = args.float()?;
+                let sound = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::Filter(filter::Filter::Delay(delay), sound)))
+            }
+            Func::Comb => {
+                let delay = args.float()?;
+                let feedback = args.float()?;
+                let sound = args.sound().unwrap()?;
+                Value::Sound(Arc::new(Sound::Filter(
+                    filter::Filter::Comb(delay, feedback),
+                    sound,
+                )))
+            }
+            Func::Concat => {
+                let mut sounds = Vec::new();
+                while args.args.len() > 0 {
+                    let len = args.float()?;
+                    let sound = args.sound().ok_or(Error::Arity {
+                        func: Func::Concat,
+                        min: 2 * sounds.len() + 2,
+                        max: None,
+                        found: 2 * sounds.len() + 1,
+                    })??;
+                    sounds.push((len, sound));
+                }
+                Value::Sound(Arc::new(Sound::concat(sounds)?))
+            }
+            Func::Flatten => Value::Sound(Arc::new(Sound::Flatten(args.sound().unwrap()?))),
+            Func::Trigger => Value::Sound(Arc::new(Sound::Trigger(args.sound().unwrap()?))),
+            Func::Shift => {
+                let offset = args.float()?;
+                Value::Sound(Arc::new(Sound::Shift(offset, args.sound().unwrap()?)))
+            }
+            Func::Stretch => {
+                let scale = args.float()?;
+                Value::Sound(Arc::new(Sound::stretch(scale, args.sound().unwrap()?)?))
+            }
+            Func::Trim => {
+                let from = args.float()?;
+                let to = args.float()?;
+                Value::Sound(Arc::new(Sound::trim(from, to, args.sound().unwrap()?)?))
+            }
+            Func::Adsr => {
+                let adsr = envelope::Adsr {
+                    attack: args.float()?,
+                    decay: args.float()?,
+                    sustain: args.float()?,
+                    release: args.float()?,
+                };
+                Value::Sound(Arc::new(Sound::Adsr(adsr, args.sound().unwrap()?)))
+            }
+            Func::Noise(color) => Value::Sound(Arc::new(Sound::Noise(*color, args.int()? as u64))),
+            Func::Closure(closure) => {
+                let mut locals = closure.captured.clone();
+                locals.extend(args.args.map(|(_, value)| value));
+                closure.body.eval(&locals)?
+            }
+        })
+    }
+}
+
+/// 定義時のローカル変数を捕らえた関数．
+/// 本体からは `captured` に続けて引数をローカル変数として参照する．
+pub struct Closure {
+    params: Vec<ty::Ty>,
+    ret: ty::Ty,
+    captured: Vec<Value>,
+    body: Arc<Expr>,
+}
+/// 同じ実体のときだけ等しい．
+impl PartialEq for Closure {
+    fn eq(&self, other: &Closure) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let ty = ty::Ty::func(self.ret.clone(), self.params.clone());
+        write!(f, "{ty:?}")
+    }
+}
+
+struct Args<'a> {
+    func: &'a Func,
+    args: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+}
+impl Args<'_> {
+    fn next(&mut self) -> (usize, Value) {
+        self.args.next().unwrap()
+    }
+    fn error(&self, pos: usize, expected: ty::Kind, found: Value) -> Error {
+        Error::Type {
+            func: self.func.clone(),
+            pos,
+            expected,
+            found,
+        }
+    }
+    fn int(&mut self) -> Result<i64, Error> {
+        let (pos, value) = self.next();
+        value
+            .into_int()
+            .map_err(|found| self.error(pos, ty::Kind::Int, found))
+    }
+    fn bool(&mut self) -> Result<bool, Error> {
+        let (pos, value) = self.next();
+        value
+            .into_bool()
+            .map_err(|found| self.error(pos, ty::Kind::Bool, found))
+    }
+    fn float(&mut self) -> Result<f64, Error> {
+        let (pos, value) = self.next();
+        value
+            .into_float()
+            .map_err(|found| self.error(pos, ty::Kind::Float, found))
+    }
+    /// 全チャンネルが同じ値のフレームは `Value::Float` のまま返す．
+    fn frame(&mut self) -> Result<Value, Error> {
+        match self.next() {
+            (_, value @ (Value::Frame(_) | Value::Float(_))) => Ok(value),
+            (pos, found) => Err(self.error(pos, ty::Kind::Frame, found)),
+        }
+    }
+    fn func(&mut self) -> Result<Func, Error> {
+        let (pos, value) = self.next();
+        value
+            .into_func()
+            .map_err(|found| self.error(pos, ty::Kind::Func, found))
+    }
+    fn sound(&mut self) -> Option<Result<Arc<Sound>, Error>> {
+        let (pos, value) = self.args.next()?;
+        Some(
+            value
+                .into_sound()
+                .map_err(|found| self.error(pos, ty::Kind::Sound, found)),
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Sound {
+    T,
+    Const(Box<Value>),
+    App(Func, Vec<Arc<Sound>>),
+    Integrate(Arc<Sound>),
+    Filter(filter::Filter, Arc<Sound>),
+    Shift(f64, Arc<Sound>),
+    Stretch(f64, Arc<Sound>),
+    /// 区間 [from, to) の外を無音にする．
+    Trim(f64, f64, Arc<Sound>),
+    /// (長さ, 音) を順に並べる．各音は自身の開始時刻を 0 として鳴る．
+    Concat(Vec<(f64, Arc<Sound>)>),
+    Mix(Vec<(f64, Arc<Sound>)>),
+    Merge(Vec<Arc<Sound>>),
+    Channel(usize, Arc<Sound>),
+    /// ゲートの音で開閉する ADSR エンベロープ．ゲートは `Value::Bool` か，正なら開いているとみなす `Value::Float`．
+    Adsr(envelope::Adsr, Arc<Sound>),
+    Curve(Arc<envelope::Curve>),
+    Noise(noise::Color, u64),
+    Sample(Arc<sample::Sample>),
+    /// 音を値とする音の，各時刻に得られた音をその時刻で評価する．
+    /// 次の出来事までは同じ状態を使い続ける．
+    Flatten(Arc<Sound>),
+    /// 音を値とする音の出来事ごとに，得られた音をその時刻を 0 として鳴らし始め，全て足し合わせる．
+    /// 出来事は区間の始まりと，得られる音が別の実体に変わったとき．
+    Trigger(Arc<Sound>),
+}
+impl Sound {
+    pub fn osc(func: Func, freq: Arc<Sound>) -> Sound {
+        Sound::App(
+            func,
+            vec![Arc::new(Sound::App(
+                Func::Mul,
+                vec![
+                    Arc::new(Sound::Const(Box::new(Value::Float(TAU)))),
+                    Arc::new(Sound::Integrate(freq)),
+                ],
+            ))],
+        )
+    }
+    pub fn trim(from: f64, to: f64, sound: Arc<Sound>) -> Result<Sound, Error> {
+        if to < from {
+            return Err(Error::Window(from, to));
+        }
+        Ok(Sound::Trim(from, to, sound))
+    }
+    pub fn stretch(scale: f64, sound: Arc<Sound>) -> Result<Sound, Error> {
+        if !(scale > 0. && scale.is_finite()) {
+            return Err(Error::Scale(scale));
+        }
+        Ok(Sound::Stretch(scale, sound))
+    }
+    pub fn concat(sounds: Vec<(f64, Arc<Sound>)>) -> Result<Sound, Error> {
+        let mut start = 0.;
+        for (len, _) in &sounds {
+            if *len < 0. {
+                return Err(Error::Window(start, start + len));
+            }
+            start += len;
+        }
+        Ok(Sound::Concat(sounds))
+    }
+    /// 鳴り終わる時刻．それ以降は無音になる．分からなければ `None`．
+    pub fn end(&self) -> Option<f64> {
+        match self {
+            Sound::Trim(_, to, _) => Some(*to),
+            Sound::Concat(sounds) => Some(sounds.iter().map(|(len, _)| len).sum()),
+            Sound::Mix(sounds) => sounds
+                .iter()
+                .map(|(start, sound)| sound.end().map(|end| start + end))
+                .try_fold(0., |max: f64, end| end.map(|end| max.max(end))),
+            Sound::Shift(offset, sound) => sound.end().map(|end| end + offset),
+            Sound::Stretch(scale, sound) => sound.end().map(|end| end * scale),
+            Sound::Sample(sample) => sample.end(),
+            _ => None,
+        }
+    }
+    pub fn sample(&self, rate: f64, n: usize) -> Result<Vec<Value>, Error> {
+        stream::Stream::new(self, rate).take(n).collect()
+    }
+}
+
+pub enum Expr {
+    Imm(Value),
+    Local(usize),
+    Call(Box<Expr>, Vec<Expr>),
+    /// 引数の型，返り値の型，本体．評価するとその時点のローカル変数を捕らえる．
+    Lambda(Vec<ty::Ty>, ty::Ty, Arc<Expr>),
+}
+impl Expr {
+    pub fn eval(&self, locals: &[Value]) -> Result<Value, Error> {
+        match *self {
+            Expr::Imm(ref value) => Ok(value.clone()),
+            Expr::Local(pos) => locals.get(pos).cloned().ok_or(Error::Local(pos)),
+            Expr::Call(ref func, ref args) => {
+                let func = func.eval(locals)?.into_func().map_err(Error::NotFunc)?;
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(locals))
+                    .collect::<Result<_, _>>()?;
+                func.call(args)
+            }
+            Expr::Lambda(ref params, ref ret, ref body) => {
+                Ok(Value::Func(Func::Closure(Arc::new(Closure {
+                    params: params.clone(),
+                    ret: ret.clone(),
+                    captured: locals.to_vec(),
+                    body: body.clone(),
+                }))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, PI, SQRT_2};
+
+    fn call(func: Func, args: Vec<Expr>) -> Expr {
+        Expr::Call(Box::new(Expr::Imm(Value::Func(func))), args)
+    }
+
+    fn floats(sound: &Sound, rate: f64, n: usize) -> Vec<f64> {
+        sound
+            .sample(rate, n)
+            .unwrap()
+            .into_iter()
+            .map(|value| value.into_float().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn eval_calls() {
+        let sin = call(Func::Sin, vec![Expr::Local(0)]);
+        let value = sin.eval(&[Value::Float(1.)]).unwrap();
+        assert_eq!(value.into_float().unwrap(), 1f64.sin());
+
+        let locals = [Value::Sound(Arc::new(Sound::T)), Value::Float(1.)];
+        let shifted = call(
+            Func::App,
+            vec![
+                Expr::Imm(Value::Func(Func::Add)),
+                Expr::Local(0),
+                call(Func::Const, vec![Expr::Local(1)]),
+            ],
+        );
+        let sound = shifted.eval(&locals).unwrap().into_sound().unwrap();
+        assert_eq!(floats(&sound, 2., 4), [1., 1.5, 2., 2.5]);
+
+        assert!(matches!(
+            call(Func::Sin, vec![Expr::Local(0)]).eval(&locals),
+            Err(Error::Type { pos: 0, .. })
+        ));
+        assert!(matches!(
+            call(Func::App, vec![]).eval(&locals),
+            Err(Error::Arity {
+                min: 1,
+                max: None,
+                found: 0,
+                ..
+            })
+        ));
+        assert!(matches!(Expr::Local(2).eval(&locals), Err(Error::Local(2))));
+        assert!(matches!(
+            Expr::Call(Box::new(Expr::Local(1)), vec![]).eval(&locals),
+            Err(Error::NotFunc(Value::Float(_)))
+        ));
+    }
+
+    #[test]
+    fn func_errors() {
+        assert!(matches!(
+            Func::Add.call(vec![Value::Float(1.)]),
+            Err(Error::Arity {
+                min: 2,
+                max: Some(2),
+                found: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Func::Lowpass.call(vec![Value::Float(1.), Value::Float(1.), Value::Float(1.)]),
+            Err(Error::Type {
+                pos: 2,
+                expected: ty::Kind::Sound,
+                ..
+            })
+        ));
+        assert!(matches!(
+            Func::Concat.call(vec![
+                Value::Float(1.),
+                Value::Sound(Arc::new(Sound::T)),
+                Value::Float(1.)
+            ]),
+            Err(Error::Arity {
+                min: 4,
+                found: 3,
+                ..
+            })
+        ));
+        let integrate = Sound::Integrate(Arc::new(Sound::Const(Box::new(Value::Func(Func::Sin)))));
+        assert!(matches!(
+            integrate.sample(1., 1),
+            Err(Error::Input {
+                expected: ty::Kind::Float,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn frames() {
+        let pan = |value: f64, position: f64| {
+            Func::Pan
+                .call(vec![Value::Float(value), Value::Float(position)])
+                .unwrap()
+                .into_frame()
+                .unwrap()
+        };
+        let close = |frame: Arc<[f64]>, expected: [f64; 2]| {
+            frame
+                .iter()
+                .zip(expected)
+                .all(|(x, y)| (x - y).abs() < 1e-12)
+        };
+        assert!(close(pan(1., -1.), [1., 0.]));
+        assert!(close(pan(1., 1.), [0., 1.]));
+        assert!(close(pan(2., 0.), [SQRT_2, SQRT_2]));
+        assert!(close(pan(1., 5.), [0., 1.]));
+
+        let frame = |x: &[f64]| Value::Frame(x.into());
+        let mono = Func::MixDown.call(vec![frame(&[1., 2.])]).unwrap();
+        assert_eq!(mono.into_float().unwrap(), 1.5);
+        let sum = Func::AddFrame
+            .call(vec![frame(&[1., 2.]), frame(&[3., 4.])])
+            .unwrap();
+        assert_eq!(*sum.into_frame().unwrap(), [4., 6.]);
+        assert!(matches!(
+            Func::AddFrame.call(vec![frame(&[1., 2.]), frame(&[1., 2., 3.])]),
+            Err(Error::Channels(2, 3))
+        ));
+    }
+
+    #[test]
+    fn ints_and_bools() {
+        let int = |func: Func, x: i64, y: i64| func.call(vec![Value::Int(x), Value::Int(y)]);
+        assert!(matches!(
+            int(Func::AddInt, i64::MAX, 1),
+            Ok(Value::Int(i64::MIN))
+        ));
+        assert!(matches!(int(Func::DivInt, -7, 2), Ok(Value::Int(-3))));
+        assert!(matches!(int(Func::RemInt, -7, 2), Ok(Value::Int(-1))));
+        assert!(matches!(
+            int(Func::DivInt, 1, 0),
+            Err(Error::DivZero(Func::DivInt))
+        ));
+        assert!(matches!(int(Func::LessEqInt, 2, 2), Ok(Value::Bool(true))));
+        assert!(matches!(
+            Func::Floor.call(vec![Value::Float(-0.5)]),
+            Ok(Value::Int(-1))
+        ));
+        assert!(matches!(
+            Func::ToFloat.call(vec![Value::Int(3)]),
+            Ok(Value::Float(x)) if x == 3.
+        ));
+        assert!(matches!(
+            Func::And.call(vec![Value::Bool(true), Value::Bool(false)]),
+            Ok(Value::Bool(false))
+        ));
+
+        let branch = |cond| Func::If.call(vec![cond, Value::Int(1), Value::Int(2)]);
+        assert!(matches!(branch(Value::Bool(false)), Ok(Value::Int(2))));
+        assert!(matches!(
+            branch(Value::Float(1.)),
+            Err(Error::Type {
+                pos: 0,
+                expected: ty::Kind::Bool,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn waveforms() {
+        for (func, expected) in [
+            (Func::Saw, [0., 0.5, -1., -0.5]),
+            (Func::Square, [1., 1., -1., -1.]),
+            (Func::Triangle, [0., 1., 0., -1.]),
+        ] {
+            let values = [0., FRAC_PI_2, PI, 3. * FRAC_PI_2].map(|phase| {
+                func.call(vec![Value::Float(phase)])
+                    .unwrap()
+                    .into_float()
+                    .unwrap()
+            });
+            assert_eq!(values, expected, "{func:?}");
+        }
+    }
+
+    #[test]
+    fn integrate_and_osc_builtins() {
+        let one = call(Func::Const, vec![Expr::Imm(Value::Float(1.))]);
+        let ramp = call(Func::Integrate, vec![one])
+            .eval(&[])
+            .unwrap()
+            .into_sound()
+            .unwrap();
+        assert_eq!(floats(&ramp, 4., 4), [0., 0.25, 0.5, 0.75]);
+
+        let freq = call(Func::Const, vec![Expr::Imm(Value::Float(1.))]);
+        let saw = call(Func::Osc, vec![Expr::Imm(Value::Func(Func::Saw)), freq]);
+        let saw = saw.eval(&[]).unwrap().into_sound().unwrap();
+        assert_eq!(floats(&saw, 4., 4), [0., 0.5, -1., -0.5]);
+    }
+
+    #[test]
+    fn filter_builtins() {
+        let filtered = |func: Func, params: &[f64], sound: Arc<Sound>| {
+            let mut args: Vec<_> = params.iter().copied().map(Value::Float).collect();
+            args.push(Value::Sound(sound));
+            func.call(args).unwrap().into_sound().unwrap()
+        };
+        // 評価開始前の入力は 0 とみなす
+        let delayed = filtered(Func::Delay, &[0.5], Arc::new(Sound::T));
+        assert_eq!(floats(&delayed, 4., 5), [0., 0., 0., 0.25, 0.5]);
+        let comb = filtered(
+            Func::Comb,
+            &[0.5, 0.5],
+            Arc::new(Sound::Const(Box::new(Value::Float(1.)))),
+        );
+        assert!(matches!(*comb, Sound::Filter(filter::Filter::Comb(..), _)));
+
+        // 8 kHz の正弦波はローパスで消え，ハイパスで残る
+        let tone = || {
+            Arc::new(Sound::osc(
+                Func::Sin,
+                Arc::new(Sound::Const(Box::new(Value::Float(8000.)))),
+            ))
+        };
+        let peak = |sound: &Sound| {
+            floats(sound, 44100., 4410)[2205..]
+                .iter()
+                .fold(0., |max: f64, x| max.max(x.abs()))
+        };
+        assert!(peak(&filtered(Func::Lowpass, &[800., 0.707], tone())) < 0.02);
+        assert!(peak(&filtered(Func::Highpass, &[800., 0.707], tone())) > 0.99);
+        assert!(peak(&filtered(Func::OnePole, &[100.], tone())) < 0.02);
+        assert!(peak(&filtered(Func::Bandpass, &[8000., 2.], tone())) > 0.99);
+    }
+
+    #[test]
+    fn concat_builtin() {
+        let concat = Func::Concat.call(vec![
+            Value::Float(0.5),
+            Value::Sound(Arc::new(Sound::T)),
+            Value::Float(0.25),
+            Value::Sound(Arc::new(Sound::Const(Box::new(Value::Float(2.))))),
+        ]);
+        let concat = concat.unwrap().into_sound().unwrap();
+        assert_eq!(floats(&concat, 4., 5), [0., 0.25, 2., 0., 0.]);
+        let empty = Func::Concat.call(vec![]).unwrap().into_sound().unwrap();
+        assert_eq!(floats(&empty, 4., 2), [0., 0.]);
+    }
+}
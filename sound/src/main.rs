@@ -1,166 +1,38 @@
-mod ty;
+use std::sync::Arc;
 
-use enum_as_inner::EnumAsInner;
+use sound::{parse, sink, ty, wav, Sound, Value};
 
-#[derive(Clone, Debug, EnumAsInner)]
-enum Value {
-    Float(f64),
-    Func(Func),
-    Sound(Sound),
-}
+const PATCH: &str = "
+    # 5 Hz のビブラートをかけた 440 Hz の正弦波
+    vibrato = app(mul, const(10.0), osc(sin, const(5.0)))
+    freq = app(add, const(440.0), vibrato)
+    out = app(mul, gain, osc(sin, freq))
+";
 
-#[derive(Clone, Debug)]
-enum Func {
-    Sin,
-    Add,
-    App,
-    Const,
-}
-impl Func {
-    fn call(&self, args: Vec<Value>) -> Value {
-        let mut args = args.into_iter();
-        let mut arg = || args.next().unwrap();
-        match self {
-            Func::Sin => {
-                let theta = arg().into_float().unwrap();
-                Value::Float(theta.sin())
-            }
-            Func::Add => {
-                let first = arg().into_float().unwrap();
-                let second = arg().into_float().unwrap();
-                Value::Float(first + second)
-            }
-            Func::App => {
-                let func = arg().into_func().unwrap();
-                let sound = args.map(|value| value.into_sound().unwrap()).collect();
-                Value::Sound(Sound::App(func, sound))
-            }
-            Func::Const => Value::Sound(Sound::Const(Box::new(arg()))),
-        }
-    }
-}
+/// `PATCH` を `patch.wav` に書き出す．`--raw s16` や `--raw f32` なら，ヘッダなしの PCM を標準出力に流す．
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let program = parse::parse(PATCH, &["gain"])?;
+    program.check(vec![ty::Ty::sound(ty::Ty::float())])?;
+    let gain = Value::Sound(Arc::new(Sound::Const(Box::new(Value::Float(0.5)))));
+    let values = program.eval(vec![gain])?;
+    let out = values.last().and_then(Value::as_sound).ok_or("no output")?;
 
-#[derive(Clone, Debug)]
-enum Sound {
-    T,
-    Const(Box<Value>),
-    App(Func, Vec<Sound>),
-}
-impl Sound {
-    fn sample(&self, rate: f64, n: usize) -> Vec<Value> {
-        match self {
-            Sound::Const(value) => vec![*value.clone(); n],
-            Sound::T => (0..n).map(|t| Value::Float((t as f64) / rate)).collect(),
-            Sound::App(func, args) => {
-                let mut args: Vec<_> = args
-                    .iter()
-                    .map(|sound| sound.sample(rate, n).into_iter())
-                    .collect();
-                (0..n)
-                    .map(|_| func.call(args.iter_mut().map(|iter| iter.next().unwrap()).collect()))
-                    .collect()
-            }
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match &args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => {
+            let file = std::io::BufWriter::new(std::fs::File::create("patch.wav")?);
+            wav::write(file, out, 44100, 1, 2., sink::Format::Int16)?;
         }
-    }
-}
-
-enum Expr {
-    Imm(Value),
-    Local(usize),
-    Call(Box<Expr>, Vec<Expr>),
-}
-impl Expr {
-    fn eval(&self, locals: &[Value]) -> Value {
-        match *self {
-            Expr::Imm(ref value) => value.clone(),
-            Expr::Local(pos) => locals[pos].clone(),
-            Expr::Call(ref func, ref args) => {
-                let func = func.eval(locals).into_func().unwrap();
-                let args = args.into_iter().map(|arg| arg.eval(locals)).collect();
-                func.call(args)
-            }
+        ["--raw", format] => {
+            let format = match *format {
+                "s16" => sink::Format::Int16,
+                "f32" => sink::Format::Float32,
+                _ => return Err(format!("unknown sample format `{format}`").into()),
+            };
+            let mut raw = sink::Raw::new(std::io::stdout().lock(), format);
+            sink::render(&mut raw, out, 44100, 1, 2.)?;
         }
+        _ => return Err("usage: sound [--raw s16|f32]".into()),
     }
-}
-
-fn main() {
-    println!(
-        "{:?}",
-        Expr::Call(
-            Box::new(Expr::Imm(Value::Func(Func::Sin))),
-            vec![Expr::Local(0)],
-        )
-        .eval(&[Value::Float(1.0)])
-    );
-    println!(
-        "{:?}",
-        Expr::Call(
-            Box::new(Expr::Imm(Value::Func(Func::App))),
-            vec![Expr::Imm(Value::Func(Func::Sin)), Expr::Local(0)],
-        )
-        .eval(&[Value::Sound(Sound::T)])
-    );
-    println!(
-        "{:?}",
-        Expr::Call(
-            Box::new(Expr::Imm(Value::Func(Func::App))),
-            vec![
-                Expr::Imm(Value::Func(Func::Add)),
-                Expr::Local(0),
-                Expr::Call(
-                    Box::new(Expr::Imm(Value::Func(Func::Const))),
-                    vec![Expr::Local(1)]
-                )
-            ],
-        )
-        .eval(&[Value::Sound(Sound::T), Value::Float(1.0)])
-        .into_sound()
-        .unwrap()
-        .sample(2., 10)
-    );
-    println!(
-        "{:?}",
-        Sound::App(
-            Func::App,
-            vec![
-                Sound::Const(Box::new(Value::Func(Func::Add))),
-                Sound::Const(Box::new(Value::Sound(Sound::T))),
-                Sound::App(Func::Const, vec![Sound::T]),
-            ]
-        )
-        .sample(1., 5)
-        .into_iter()
-        .map(|sound| sound.into_sound().unwrap().sample(1., 5))
-        .collect::<Vec<_>>()
-    );
-
-    println!();
-    println!(
-        "Const[#0]: {:?}",
-        ty::Func {
-            args: vec![ty::Arg::Expr(ty::Expr::Var(0))],
-            ret: ty::Expr::App(ty::Kind::Sound, vec![ty::Arg::Expr(ty::Expr::Var(0))]),
-        }
-    );
-    let app_ty = ty::Func {
-        args: vec![
-            ty::Arg::Expr(ty::Expr::App(
-                ty::Kind::Func,
-                vec![
-                    ty::Arg::Expr(ty::Expr::Var(0)),
-                    ty::Arg::Expand(ty::Expr::Range(1, 0)),
-                ],
-            )),
-            ty::Arg::Expand(ty::Expr::App(
-                ty::Kind::Sound,
-                vec![ty::Arg::Expr(ty::Expr::Range(1, 0))],
-            )),
-        ],
-        ret: ty::Expr::App(ty::Kind::Sound, vec![ty::Arg::Expr(ty::Expr::Var(0))]),
-    };
-    println!("App[#0, #1:#-0]: {app_ty:?}");
-    println!(
-        "App[Int, Float, Bool]: {:?}",
-        app_ty.eval(&[ty::Ty::int(), ty::Ty::float(), ty::Ty::bool()])
-    );
+    Ok(())
 }
@@ -0,0 +1,438 @@
+use std::{collections::VecDeque, f64::consts::PI, fmt, io};
+
+use super::*;
+use crate::{filter, sink::AudioSink, stream::BLOCK};
+
+fn amplitude(db: f64) -> f64 {
+    10f64.powf(db / 20.)
+}
+
+/// 全体を受け取ってから，最大の振幅が `peak` [dBFS] になるように揃えて書き出す．
+pub struct Normalize<S> {
+    inner: S,
+    peak: f64,
+    channels: usize,
+    samples: Vec<f64>,
+    gain: f64,
+}
+impl<S: AudioSink> Normalize<S> {
+    pub fn new(inner: S, peak: f64) -> Normalize<S> {
+        Normalize {
+            inner,
+            peak,
+            channels: 1,
+            samples: Vec::new(),
+            gain: 1.,
+        }
+    }
+    pub fn gain(&self) -> f64 {
+        self.gain
+    }
+}
+impl<S: AudioSink> AudioSink for Normalize<S> {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        sink::check_channels(channels)?;
+        self.channels = channels as usize;
+        self.samples.clear();
+        self.samples.reserve(frames * self.channels);
+        self.inner.start(rate, channels, frames)
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        self.samples.extend_from_slice(block);
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        let peak = self.samples.iter().fold(0., |max: f64, x| max.max(x.abs()));
+        self.gain = if peak > 0. {
+            amplitude(self.peak) / peak
+        } else {
+            1.
+        };
+        let mut block = Vec::with_capacity(BLOCK * self.channels);
+        for chunk in self.samples.chunks(BLOCK * self.channels) {
+            block.clear();
+            block.extend(chunk.iter().map(|x| x * self.gain));
+            self.inner.write(&block)?;
+        }
+        self.inner.finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    /// 超えないようにする振幅 [dBFS]．
+    pub ceiling: f64,
+    /// 先読みする長さ [s]．この間をかけて音量を下げる．
+    pub lookahead: f64,
+    /// 音量を戻す時定数 [s]．
+    pub release: f64,
+}
+impl Default for Limit {
+    fn default() -> Limit {
+        Limit {
+            ceiling: -1.,
+            lookahead: 0.005,
+            release: 0.05,
+        }
+    }
+}
+
+/// 先読みした分だけ遅らせて，振幅が上限を超える前に滑らかに音量を下げる．
+/// 遅れは取り除いて，受け取ったのと同じ長さを書き出す．
+pub struct Limiter<S> {
+    inner: S,
+    limit: Limit,
+    ceiling: f64,
+    channels: usize,
+    len: usize,
+    release: f64,
+    frames: usize,
+    /// 先読みの範囲の (フレーム番号, 上限に収めるための倍率) を倍率の昇順に並べる．
+    required: VecDeque<(usize, f64)>,
+    delayed: VecDeque<f64>,
+    /// 直近 `len` フレームの倍率．平均して滑らかにする．
+    held: VecDeque<f64>,
+    out: Vec<f64>,
+}
+impl<S: AudioSink> Limiter<S> {
+    pub fn new(inner: S, limit: Limit) -> Limiter<S> {
+        Limiter {
+            inner,
+            limit,
+            ceiling: amplitude(limit.ceiling),
+            channels: 1,
+            len: 1,
+            release: 1.,
+            frames: 0,
+            required: VecDeque::new(),
+            delayed: VecDeque::new(),
+            held: VecDeque::new(),
+            out: Vec::new(),
+        }
+    }
+    fn push(&mut self, frame: &[f64]) {
+        let peak = frame.iter().fold(0., |max: f64, x| max.max(x.abs()));
+        let required = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.
+        };
+        let i = self.frames;
+        self.frames += 1;
+        while self.required.back().is_some_and(|&(_, r)| r >= required) {
+            self.required.pop_back();
+        }
+        self.required.push_back((i, required));
+        while self
+            .required
+            .front()
+            .is_some_and(|&(j, _)| j + self.len < i)
+        {
+            self.required.pop_front();
+        }
+        self.delayed.extend(frame);
+
+        // `len` フレーム前のフレームの倍率は，その後 `len` フレームに必要な倍率を超えない．
+        // 書き出すフレームは直近 `len` 個の倍率のどれの先読みの範囲にも入るので，平均しても上限に収まる
+        let prev = *self.held.back().unwrap();
+        let hold = self.required[0].1.min(prev + (1. - prev) * self.release);
+        self.held.pop_front();
+        self.held.push_back(hold);
+        if i < self.len {
+            return;
+        }
+        let gain = self.held.iter().sum::<f64>() / self.len as f64;
+        for x in self.delayed.drain(..self.channels) {
+            // 丸め誤差で上限をわずかに超えないように抑える
+            self.out.push((x * gain).clamp(-self.ceiling, self.ceiling));
+        }
+    }
+}
+impl<S: AudioSink> AudioSink for Limiter<S> {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        sink::check_channels(channels)?;
+        self.channels = channels as usize;
+        self.len = ((self.limit.lookahead * rate as f64).round() as usize).max(1);
+        self.release = 1. - (-1. / (self.limit.release * rate as f64)).exp();
+        self.frames = 0;
+        self.required.clear();
+        self.delayed.clear();
+        self.held.clear();
+        self.held.extend(std::iter::repeat_n(1., self.len));
+        self.inner.start(rate, channels, frames)
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        self.out.clear();
+        for frame in block.chunks(self.channels) {
+            self.push(frame);
+        }
+        self.inner.write(&self.out)
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.clear();
+        let silence = vec![0.; self.channels];
+        for _ in 0..self.len {
+            self.push(&silence);
+        }
+        self.inner.write(&self.out)?;
+        self.inner.finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Report {
+    /// 最大の振幅 [dBFS]．無音なら負の無限大．
+    pub peak: f64,
+    /// ITU-R BS.1770 のゲートをかけた統合ラウドネス [LUFS]．
+    /// 短すぎるか静かすぎて測れなければ `None`．各チャンネルの重みは 1 とする．
+    pub loudness: Option<f64>,
+}
+impl Report {
+    pub fn clipped(&self) -> bool {
+        self.peak > 0.
+    }
+}
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "peak {:.2} dBFS", self.peak)?;
+        if self.clipped() {
+            write!(f, " (clipped)")?;
+        }
+        match self.loudness {
+            Some(loudness) => write!(f, ", loudness {loudness:.2} LUFS"),
+            None => write!(f, ", loudness unmeasurable"),
+        }
+    }
+}
+
+pub struct Meter<S> {
+    inner: S,
+    peak: f64,
+    filters: Vec<[filter::State; 2]>,
+    channel: usize,
+    /// 100 ms ごとの，K 特性をかけたサンプルの二乗和．
+    energies: Vec<f64>,
+    sum: f64,
+    count: usize,
+    len: usize,
+}
+impl<S: AudioSink> Meter<S> {
+    pub fn new(inner: S) -> Meter<S> {
+        Meter {
+            inner,
+            peak: 0.,
+            filters: Vec::new(),
+            channel: 0,
+            energies: Vec::new(),
+            sum: 0.,
+            count: 0,
+            len: 1,
+        }
+    }
+    pub fn report(&self) -> Report {
+        Report {
+            peak: 20. * self.peak.log10(),
+            loudness: self.loudness(),
+        }
+    }
+    /// 75% ずつ重ねた 400 ms の区間の平均二乗を，-70 LUFS と相対 -10 LU のゲートで選んで平均する．
+    fn loudness(&self) -> Option<f64> {
+        let lufs = |z: f64| -0.691 + 10. * z.log10();
+        let blocks: Vec<_> = self
+            .energies
+            .windows(4)
+            .map(|w| w.iter().sum::<f64>() / (4 * self.len) as f64)
+            .filter(|&z| lufs(z) > -70.)
+            .collect();
+        let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+        if blocks.is_empty() {
+            return None;
+        }
+        let threshold = lufs(mean(&blocks)) - 10.;
+        let gated: Vec<_> = blocks
+            .into_iter()
+            .filter(|&z| lufs(z) > threshold)
+            .collect();
+        Some(lufs(mean(&gated)))
+    }
+}
+
+fn k_weighting(rate: f64) -> [filter::State; 2] {
+    let biquad = |b: [f64; 3], a: [f64; 3]| filter::State::Biquad {
+        b: b.map(|b| b / a[0]),
+        a: [a[1] / a[0], a[2] / a[0]],
+        x: [0.; 2],
+        y: [0.; 2],
+    };
+    let k = (PI * 1681.974450955533 / rate).tan();
+    let q = 0.7071752369554196;
+    let vh = 10f64.powf(3.999843853973347 / 20.);
+    let vb = vh.powf(0.4996667741545416);
+    let shelf = biquad(
+        [
+            vh + vb * k / q + k * k,
+            2. * (k * k - vh),
+            vh - vb * k / q + k * k,
+        ],
+        [1. + k / q + k * k, 2. * (k * k - 1.), 1. - k / q + k * k],
+    );
+    let k = (PI * 38.13547087602444 / rate).tan();
+    let q = 0.5003270373238773;
+    // 分子は正規化せず，997 Hz で -3.01 LUFS になる libebur128 の係数に合わせる
+    let a0 = 1. + k / q + k * k;
+    let highpass = biquad(
+        [a0, -2. * a0, a0],
+        [a0, 2. * (k * k - 1.), 1. - k / q + k * k],
+    );
+    [shelf, highpass]
+}
+
+impl<S: AudioSink> AudioSink for Meter<S> {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        sink::check_channels(channels)?;
+        self.peak = 0.;
+        self.filters = (0..channels).map(|_| k_weighting(rate as f64)).collect();
+        self.channel = 0;
+        self.energies.clear();
+        self.sum = 0.;
+        self.count = 0;
+        self.len = ((0.1 * rate as f64).round() as usize).max(1);
+        self.inner.start(rate, channels, frames)
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        for &x in block {
+            self.peak = self.peak.max(x.abs());
+            let [shelf, highpass] = &mut self.filters[self.channel];
+            let y = highpass.process(shelf.process(x));
+            self.sum += y * y;
+            self.channel += 1;
+            if self.channel == self.filters.len() {
+                self.channel = 0;
+                self.count += 1;
+                if self.count == self.len {
+                    self.energies.push(self.sum);
+                    self.sum = 0.;
+                    self.count = 0;
+                }
+            }
+        }
+        self.inner.write(block)
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.inner.finish()
+    }
+}
+
+/// 出力段の設定．どちらも `None` ならそのまま書き出す．
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Options {
+    /// 最大の振幅を揃える値 [dBFS]．
+    pub normalize: Option<f64>,
+    pub limit: Option<Limit>,
+}
+
+pub fn render(
+    sink: &mut (impl AudioSink + ?Sized),
+    sound: &Sound,
+    rate: u32,
+    channels: u16,
+    duration: f64,
+    options: &Options,
+) -> Result<Report, sink::Error> {
+    let mut meter = Meter::new(sink);
+    let mut stage: Box<dyn AudioSink + '_> = Box::new(&mut meter);
+    if let Some(limit) = options.limit {
+        stage = Box::new(Limiter::new(stage, limit));
+    }
+    if let Some(peak) = options.normalize {
+        stage = Box::new(Normalize::new(stage, peak));
+    }
+    sink::render(&mut stage, sound, rate, channels, duration)?;
+    drop(stage);
+    Ok(meter.report())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::Buffer;
+
+    fn sine(freq: f64, gain: f64) -> Sound {
+        Sound::App(
+            Func::Mul,
+            vec![
+                Arc::new(Sound::Const(Box::new(Value::Float(gain)))),
+                Arc::new(Sound::osc(
+                    Func::Sin,
+                    Arc::new(Sound::Const(Box::new(Value::Float(freq)))),
+                )),
+            ],
+        )
+    }
+
+    fn peak(buffer: &Buffer) -> f64 {
+        buffer.samples.iter().fold(0., |max: f64, x| max.max(x.abs()))
+    }
+
+    #[test]
+    fn normalize_and_limit() {
+        let mut buffer = Buffer::default();
+        let options = Options {
+            normalize: Some(-6.),
+            limit: None,
+        };
+        let report = render(&mut buffer, &sine(100., 0.1), 8000, 1, 1., &options).unwrap();
+        assert!((peak(&buffer) - amplitude(-6.)).abs() < 1e-9);
+        assert!((report.peak + 6.).abs() < 1e-6);
+
+        let options = Options {
+            normalize: None,
+            limit: Some(Limit {
+                ceiling: -1.,
+                lookahead: 0.005,
+                release: 0.05,
+            }),
+        };
+        let report = render(&mut buffer, &sine(100., 2.), 8000, 2, 1., &options).unwrap();
+        assert_eq!(buffer.samples.len(), 2 * 8000);
+        assert!(peak(&buffer) <= amplitude(-1.));
+        assert!(report.peak > -1.5);
+    }
+
+    #[test]
+    fn limiter_removes_lookahead_delay() {
+        let sound = sine(997., 0.5);
+        let mut limited = Buffer::default();
+        let limiter = &mut Limiter::new(&mut limited, Limit::default());
+        sink::render(limiter, &sound, 44100, 1, 0.01).unwrap();
+        let mut plain = Buffer::default();
+        sink::render(&mut plain, &sound, 44100, 1, 0.01).unwrap();
+        assert_eq!(limited.samples, plain.samples);
+    }
+
+    #[test]
+    fn loudness_of_full_scale_sine() {
+        let mut buffer = Buffer::default();
+        let report = render(&mut buffer, &sine(997., 1.), 48000, 1, 3., &Options::default());
+        let loudness = report.unwrap().loudness.unwrap();
+        assert!((loudness + 3.01).abs() < 0.05, "{loudness}");
+    }
+
+    #[test]
+    fn zero_channels_is_an_error() {
+        assert!(Normalize::new(Buffer::default(), 0.).start(8000, 0, 8).is_err());
+        let limit = Limit {
+            ceiling: -1.,
+            lookahead: 0.005,
+            release: 0.05,
+        };
+        assert!(Limiter::new(Buffer::default(), limit).start(8000, 0, 8).is_err());
+        assert!(Meter::new(Buffer::default()).start(8000, 0, 8).is_err());
+        let options = Options {
+            normalize: Some(0.),
+            limit: Some(limit),
+        };
+        let report = render(&mut Buffer::default(), &Sound::T, 8000, 0, 1., &options);
+        assert!(report.is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+use super::*;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    NotMidi,
+    Truncated,
+    /// 対応していないフォーマット．0 と 1 だけに対応する．
+    Format(u16),
+    Smpte,
+    Status(u8),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::NotMidi => write!(f, "not a standard MIDI file"),
+            Error::Truncated => write!(f, "truncated MIDI file"),
+            Error::Format(format) => write!(f, "unsupported MIDI file format {format}"),
+            Error::Smpte => write!(f, "SMPTE time division is not supported"),
+            Error::Status(byte) => write!(f, "unexpected data byte {byte:#04x} without status"),
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// 鳴らす音．`start` と `duration` は 4 分音符を 1 拍として数える．
+#[derive(Clone, Debug)]
+pub struct Note {
+    pub start: f64,
+    pub duration: f64,
+    pub channel: u8,
+    pub key: u8,
+    pub velocity: u8,
+}
+
+#[derive(Debug)]
+pub struct Midi {
+    /// テンポの指定がなければ 120 BPM．
+    pub tempo: score::Tempo,
+    pub notes: Vec<Note>,
+}
+
+/// Standard MIDI File を読み込む．フォーマット 1 では全トラックのテンポと音をまとめる．
+pub fn read(mut reader: impl Read) -> Result<Midi, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if !bytes.starts_with(b"MThd") {
+        return Err(Error::NotMidi);
+    }
+    let mut rest = &bytes[..];
+    let header = match chunk(&mut rest)? {
+        (b"MThd", header) if header.len() >= 6 => header,
+        _ => return Err(Error::NotMidi),
+    };
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    if format > 1 {
+        return Err(Error::Format(format));
+    }
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if division & 0x8000 != 0 {
+        return Err(Error::Smpte);
+    }
+    let division = division.max(1) as f64;
+
+    let mut tempos = Vec::new();
+    let mut notes = Vec::new();
+    while !rest.is_empty() {
+        // 知らないチャンクは読み飛ばす
+        if let (b"MTrk", track) = chunk(&mut rest)? {
+            read_track(track, &mut tempos, &mut notes)?;
+        }
+    }
+
+    let mut tempo = score::Tempo::new(120.);
+    tempos.sort_by_key(|&(tick, _)| tick);
+    for (tick, micros) in tempos {
+        tempo.set(tick as f64 / division, 60e6 / micros as f64);
+    }
+    notes.sort_by_key(|&(start, ..)| start);
+    let notes = notes
+        .into_iter()
+        .map(|(start, end, channel, key, velocity)| Note {
+            start: start as f64 / division,
+            duration: (end - start) as f64 / division,
+            channel,
+            key,
+            velocity,
+        })
+        .collect();
+    Ok(Midi { tempo, notes })
+}
+
+fn chunk<'a>(rest: &mut &'a [u8]) -> Result<(&'a [u8], &'a [u8]), Error> {
+    if rest.len() < 8 {
+        return Err(Error::Truncated);
+    }
+    let len = u32::from_be_bytes(rest[4..8].try_into().unwrap()) as usize;
+    let body = rest.get(8..8 + len).ok_or(Error::Truncated)?;
+    let kind = &rest[..4];
+    *rest = &rest[8 + len..];
+    Ok((kind, body))
+}
+
+/// トラックを読み，(tick, 4 分音符あたりのマイクロ秒) のテンポ変更と
+/// (開始 tick, 終了 tick, チャンネル, キー, ベロシティ) の音を追加する．
+#[allow(clippy::type_complexity)]
+fn read_track(
+    mut track: &[u8],
+    tempos: &mut Vec<(u64, u32)>,
+    notes: &mut Vec<(u64, u64, u8, u8, u8)>,
+) -> Result<(), Error> {
+    let byte = |track: &mut &[u8]| -> Result<u8, Error> {
+        let (&first, rest) = track.split_first().ok_or(Error::Truncated)?;
+        *track = rest;
+        Ok(first)
+    };
+    let mut tick = 0;
+    let mut status = None;
+    // 鳴っている音の (開始 tick, チャンネル, キー, ベロシティ)
+    let mut held: Vec<(u64, u8, u8, u8)> = Vec::new();
+    while !track.is_empty() {
+        tick += vlq(&mut track)?;
+        let first = byte(&mut track)?;
+        let (kind, data) = if first & 0x80 != 0 {
+            (first, None)
+        } else {
+            (status.ok_or(Error::Status(first))?, Some(first))
+        };
+        match kind {
+            0xff => {
+                let meta = byte(&mut track)?;
+                let len = vlq(&mut track)? as usize;
+                let body = track.get(..len).ok_or(Error::Truncated)?;
+                track = &track[len..];
+                match (meta, body) {
+                    (0x51, &[a, b, c]) => {
+                        tempos.push((tick, u32::from_be_bytes([0, a, b, c]).max(1)))
+                    }
+                    (0x2f, _) => break,
+                    _ => {}
+                }
+                status = None;
+                continue;
+            }
+            0xf0 | 0xf7 => {
+                let len = vlq(&mut track)? as usize;
+                track = track.get(len..).ok_or(Error::Truncated)?;
+                status = None;
+                continue;
+            }
+            _ => status = Some(kind),
+        }
+        let first = match data {
+            Some(data) => data,
+            None => byte(&mut track)?,
+        };
+        let channel = kind & 0x0f;
+        match kind & 0xf0 {
+            0xc0 | 0xd0 => {}
+            kind => {
+                let second = byte(&mut track)?;
+                match (kind, second) {
+                    (0x90, velocity) if velocity > 0 => held.push((tick, channel, first, velocity)),
+                    (0x80, _) | (0x90, _) => {
+                        // 同じキーが重なっていれば先に鳴らしたものから止める
+                        if let Some(i) = held
+                            .iter()
+                            .position(|&(_, c, key, _)| c == channel && key == first)
+                        {
+                            let (start, channel, key, velocity) = held.remove(i);
+                            notes.push((start, tick, channel, key, velocity));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    // 止められなかった音はトラックの終わりで止める
+    for (start, channel, key, velocity) in held {
+        notes.push((start, tick, channel, key, velocity));
+    }
+    Ok(())
+}
+
+fn vlq(track: &mut &[u8]) -> Result<u64, Error> {
+    let mut value = 0;
+    for _ in 0..4 {
+        let (&byte, rest) = track.split_first().ok_or(Error::Truncated)?;
+        *track = rest;
+        value = value << 7 | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::Truncated)
+}
+
+impl Midi {
+    /// 各音をチャンネルごとに `instrument` で選んだ楽器で鳴らす楽譜にする．
+    /// 楽器には長さ [s] に続けて周波数 [Hz] と [0, 1] に揃えたベロシティを渡す．
+    pub fn score(&self, instrument: impl Fn(u8) -> Func) -> score::Score {
+        score::Score {
+            tempo: self.tempo.clone(),
+            events: self
+                .notes
+                .iter()
+                .map(|note| score::Event {
+                    start: note.start,
+                    duration: note.duration,
+                    instrument: instrument(note.channel),
+                    params: vec![
+                        Value::Float(score::freq(note.key as f64)),
+                        Value::Float(note.velocity as f64 / 127.),
+                    ],
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMAT0: &[u8] = include_bytes!("../testdata/format0.mid");
+    const FORMAT1: &[u8] = include_bytes!("../testdata/format1.mid");
+
+    fn notes(midi: &Midi) -> Vec<(f64, f64, u8, u8, u8)> {
+        midi.notes
+            .iter()
+            .map(|note| (note.start, note.duration, note.channel, note.key, note.velocity))
+            .collect()
+    }
+
+    #[test]
+    fn format0() {
+        let midi = read(FORMAT0).unwrap();
+        assert_eq!(notes(&midi), [(0., 1., 0, 60, 100), (1., 2., 0, 64, 80)]);
+        assert_eq!(midi.tempo.seconds(2.), 1.);
+        assert_eq!(midi.tempo.seconds(3.), 2.);
+    }
+
+    #[test]
+    fn format1() {
+        let midi = read(FORMAT1).unwrap();
+        assert_eq!(notes(&midi), [(1., 1., 2, 67, 127), (1.5, 0.5, 2, 67, 64)]);
+        assert_eq!(midi.tempo.seconds(1.), 1.);
+    }
+
+    fn patched(at: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut file = FORMAT0.to_vec();
+        file[at..at + bytes.len()].copy_from_slice(bytes);
+        file
+    }
+
+    fn with_track(track: &[u8]) -> Vec<u8> {
+        let mut file = FORMAT0[..14].to_vec();
+        file.extend_from_slice(b"MTrk");
+        file.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        file.extend_from_slice(track);
+        file
+    }
+
+    #[test]
+    fn failures() {
+        assert!(matches!(read(&b"RIFF"[..]), Err(Error::NotMidi)));
+        assert!(matches!(read(&b""[..]), Err(Error::NotMidi)));
+        assert!(matches!(read(&b"MThd\0\0\0\x02\0\0"[..]), Err(Error::NotMidi)));
+        assert!(matches!(read(&b"MThd\0\0"[..]), Err(Error::Truncated)));
+        assert!(matches!(
+            read(&FORMAT0[..FORMAT0.len() - 5]),
+            Err(Error::Truncated)
+        ));
+        assert!(matches!(read(&patched(8, &[0, 2])[..]), Err(Error::Format(2))));
+        assert!(matches!(read(&patched(12, &[0xe2, 0x50])[..]), Err(Error::Smpte)));
+        assert!(matches!(
+            read(&with_track(&[0x00, 0x3c, 0x64])[..]),
+            Err(Error::Status(0x3c))
+        ));
+        assert!(matches!(
+            read(&with_track(&[0x00, 0x90, 0x3c])[..]),
+            Err(Error::Truncated)
+        ));
+        assert!(matches!(
+            read(&with_track(&[0xff, 0xff, 0xff, 0xff, 0x00])[..]),
+            Err(Error::Truncated)
+        ));
+    }
+}
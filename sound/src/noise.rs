@@ -0,0 +1,85 @@
+/// 雑音の種類．どれも振幅がおおよそ [-1, 1] に収まる．
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    White,
+    /// -3 dB/oct の雑音．44100 Hz 向けの Paul Kellet のフィルタで近似する．
+    Pink,
+    /// -6 dB/oct の雑音．白色雑音を漏れのある積分器に通す．
+    Brown,
+}
+
+impl Color {
+    pub fn state(self, seed: u64) -> State {
+        State {
+            color: self,
+            rng: seed,
+            history: [0.; 7],
+        }
+    }
+}
+
+pub struct State {
+    color: Color,
+    rng: u64,
+    history: [f64; 7],
+}
+
+impl State {
+    /// SplitMix64 で [-1, 1) の一様乱数を作る．
+    fn white(&mut self) -> f64 {
+        self.rng = self.rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 52) as f64 - 1.
+    }
+    pub fn sample(&mut self) -> f64 {
+        let white = self.white();
+        let b = &mut self.history;
+        match self.color {
+            Color::White => white,
+            Color::Pink => {
+                b[0] = 0.99886 * b[0] + white * 0.0555179;
+                b[1] = 0.99332 * b[1] + white * 0.0750759;
+                b[2] = 0.96900 * b[2] + white * 0.1538520;
+                b[3] = 0.86650 * b[3] + white * 0.3104856;
+                b[4] = 0.55000 * b[4] + white * 0.5329522;
+                b[5] = -0.7616 * b[5] - white * 0.0168980;
+                let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+                b[6] = white * 0.115926;
+                pink * 0.11
+            }
+            Color::Brown => {
+                b[0] = (b[0] + 0.02 * white) / 1.02;
+                b[0] * 3.5
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn take(color: Color, seed: u64) -> Vec<f64> {
+        let mut state = color.state(seed);
+        (0..1000).map(|_| state.sample()).collect()
+    }
+
+    #[test]
+    fn seed_determines_output() {
+        for color in [Color::White, Color::Pink, Color::Brown] {
+            assert_eq!(take(color, 7), take(color, 7));
+            assert_ne!(take(color, 7), take(color, 8));
+        }
+    }
+
+    #[test]
+    fn white_is_in_range() {
+        let white = take(Color::White, 1);
+        assert!(white.iter().all(|x| (-1.0..1.).contains(x)));
+        let mean = white.iter().sum::<f64>() / white.len() as f64;
+        assert!(mean.abs() < 0.1);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+};
+
+use super::*;
+use crate::{
+    sink::AudioSink,
+    stream::{first_index, sample_range, Cursor, BLOCK},
+};
+
+/// 1 スレッドが一度に評価するブロック数．
+/// これだけ評価するごとに書き出すので，使うメモリは音の長さによらない．
+const GROUP: usize = 16;
+
+/// `sink::render` と同じものを `threads` 本のスレッドで書き出す．
+/// 状態を持たない音は時間で区切って，`Sound::Mix` は音ごとに分けて評価し，
+/// 足し合わせる順序を揃えるので結果は 1 スレッドで書き出したものと一致する．
+/// どちらでもなければ 1 スレッドで評価する．
+pub fn render(
+    sink: &mut (impl AudioSink + ?Sized),
+    sound: &Sound,
+    rate: u32,
+    channels: u16,
+    duration: f64,
+    threads: usize,
+) -> Result<(), sink::Error> {
+    if channels == 0 {
+        return Err(Error::NoChannels.into());
+    }
+    let job = match sound {
+        _ if stateless(sound) => Job::Stateless(sound),
+        Sound::Mix(sounds) => Job::Mix(sounds),
+        _ => return sink::render(sink, sound, rate, channels, duration),
+    };
+    let threads = threads.max(1);
+    let n = (duration * rate as f64).round() as usize;
+    sink.start(rate, channels, n)?;
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|k| {
+                let (send_range, ranges) = channel();
+                let (results, recv_parts) = channel();
+                scope.spawn(move || job.run(rate as f64, n, k, threads, ranges, results));
+                (send_range, recv_parts)
+            })
+            .collect();
+        let mut block = vec![0.; BLOCK * channels as usize];
+        for from in (0..n).step_by(GROUP * BLOCK * threads) {
+            let to = n.min(from + GROUP * BLOCK * threads);
+            for (send_range, _) in &workers {
+                send_range.send((from, to)).unwrap();
+            }
+            let mut parts = Vec::new();
+            for (_, recv_parts) in &workers {
+                parts.extend(recv_parts.recv().unwrap()?);
+            }
+            parts.sort_by_key(|part| part.0);
+            let values = job.merge(parts, from, to)?;
+            for (i, values) in values.chunks(BLOCK).enumerate() {
+                let block = &mut block[..values.len() * channels as usize];
+                for (j, (frame, value)) in block
+                    .chunks_mut(channels as usize)
+                    .zip(values.iter().cloned())
+                    .enumerate()
+                {
+                    stream::fill_frame(frame, from + i * BLOCK + j, value)?;
+                }
+                sink.write(block)?;
+            }
+        }
+        Ok::<_, sink::Error>(())
+    })?;
+    sink.finish()?;
+    Ok(())
+}
+
+/// 並び順，書き始める位置，値．
+type Part = (usize, usize, Vec<Value>);
+
+#[derive(Clone, Copy)]
+enum Job<'a> {
+    Stateless(&'a Sound),
+    Mix(&'a [(f64, Arc<Sound>)]),
+}
+
+impl Job<'_> {
+    /// `k` 番目のスレッドで，送られてきた区間のうち受け持つ分を評価して返す．
+    fn run(
+        self,
+        rate: f64,
+        n: usize,
+        k: usize,
+        threads: usize,
+        ranges: Receiver<(usize, usize)>,
+        results: Sender<Result<Vec<Part>, Error>>,
+    ) {
+        // 音の状態は区間をまたいで持ち越す
+        let mut voices: Vec<_> = match self {
+            Job::Stateless(_) => Vec::new(),
+            Job::Mix(sounds) => sounds
+                .iter()
+                .enumerate()
+                .skip(k)
+                .step_by(threads)
+                .map(|(i, (start, sound))| {
+                    let from = first_index(*start, rate).min(n);
+                    let to = match sound.end() {
+                        Some(end) => first_index(start + end, rate).clamp(from, n),
+                        None => n,
+                    };
+                    (i, from, to, *start, &**sound, None)
+                })
+                .collect(),
+        };
+        for (from, to) in ranges {
+            let parts = match self {
+                Job::Stateless(sound) => {
+                    // ブロックの区切りに揃えて時間を分ける
+                    let from = from + k * GROUP * BLOCK;
+                    let to = to.min(from + GROUP * BLOCK);
+                    if from < to {
+                        sample_range(sound, rate, from, to, 0.)
+                            .map(|values| vec![(k, from, values)])
+                    } else {
+                        Ok(Vec::new())
+                    }
+                }
+                Job::Mix(_) => voices
+                    .iter_mut()
+                    .filter(|voice| voice.1.max(from) < voice.2.min(to))
+                    .map(|(i, start, end, offset, sound, cursor)| {
+                        let mut values = Vec::new();
+                        cursor
+                            .get_or_insert_with(|| Cursor::new(sound, rate, *start, *offset))
+                            .fill((*end).min(to), &mut values)?;
+                        if *end <= to {
+                            *cursor = None;
+                        }
+                        Ok((*i, (*start).max(from), values))
+                    })
+                    .collect(),
+            };
+            if results.send(parts).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// 各スレッドの結果を `from` から `to` の手前までの値にまとめる．
+    fn merge(self, parts: Vec<Part>, from: usize, to: usize) -> Result<Vec<Value>, Error> {
+        match self {
+            Job::Stateless(_) => Ok(parts
+                .into_iter()
+                .flat_map(|(_, _, values)| values)
+                .collect()),
+            Job::Mix(_) => {
+                // 1 スレッドのときと同じ順に足す
+                let mut values = vec![Value::Float(0.); to - from];
+                for (_, at, voice) in parts {
+                    for (slot, value) in values[at - from..].iter_mut().zip(&voice) {
+                        *slot = stream::mix(slot, value)?;
+                    }
+                }
+                Ok(values)
+            }
+        }
+    }
+}
+
+/// 時刻だけで値が決まり，どこから評価しても同じ値になるか．
+fn stateless(sound: &Sound) -> bool {
+    match sound {
+        Sound::T | Sound::Const(_) | Sound::Sample(_) | Sound::Curve(_) => true,
+        Sound::App(_, sounds) | Sound::Merge(sounds) => sounds.iter().all(|sound| stateless(sound)),
+        Sound::Shift(_, sound)
+        | Sound::Stretch(_, sound)
+        | Sound::Trim(_, _, sound)
+        | Sound::Channel(_, sound) => stateless(sound),
+        Sound::Concat(sounds) | Sound::Mix(sounds) => {
+            sounds.iter().all(|(_, sound)| stateless(sound))
+        }
+        Sound::Integrate(_)
+        | Sound::Filter(..)
+        | Sound::Noise(..)
+        | Sound::Adsr(..)
+        | Sound::Flatten(_)
+        | Sound::Trigger(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::Buffer;
+
+    fn constant(value: Value) -> Arc<Sound> {
+        Arc::new(Sound::Const(Box::new(value)))
+    }
+
+    fn osc(func: Func, freq: f64) -> Arc<Sound> {
+        Arc::new(Sound::osc(func, constant(Value::Float(freq))))
+    }
+
+    fn assert_same(sound: &Sound, channels: u16, duration: f64) {
+        let mut single = Buffer::default();
+        sink::render(&mut single, sound, 8000, channels, duration).unwrap();
+        for threads in [3, 4] {
+            let mut multi = Buffer::default();
+            render(&mut multi, sound, 8000, channels, duration, threads).unwrap();
+            assert_eq!(single.samples.len(), multi.samples.len());
+            assert!(single
+                .samples
+                .iter()
+                .zip(&multi.samples)
+                .all(|(x, y)| x.to_bits() == y.to_bits()));
+        }
+    }
+
+    #[test]
+    fn stateless_sounds() {
+        let sine = Sound::App(
+            Func::Sin,
+            vec![Arc::new(Sound::App(
+                Func::Mul,
+                vec![constant(Value::Float(440. * TAU)), Arc::new(Sound::T)],
+            ))],
+        );
+        assert!(stateless(&sine));
+        assert_same(&sine, 1, 1.);
+        let stereo = Sound::Merge(vec![Arc::new(sine), Arc::new(Sound::T)]);
+        assert_same(&stereo, 2, 1.);
+    }
+
+    #[test]
+    fn mixed_voices() {
+        let note = |freq| {
+            Arc::new(Sound::Trim(
+                0.,
+                0.3,
+                Arc::new(Sound::Filter(
+                    filter::Filter::OnePole(1000.),
+                    osc(Func::Saw, freq),
+                )),
+            ))
+        };
+        let mix = Sound::Mix(vec![
+            (0., note(220.)),
+            (0.1, note(330.)),
+            (0.1, note(440.)),
+            (0.5, Arc::new(Sound::Noise(noise::Color::Pink, 1))),
+            (0.9, note(550.)),
+        ]);
+        assert!(!stateless(&mix));
+        assert_same(&mix, 1, 1.);
+        assert_same(&mix, 2, 1.5);
+    }
+
+    #[test]
+    fn long_sounds_are_written_in_groups() {
+        // 1 回に評価する区間をまたいで鳴る音
+        let note = |freq| {
+            Arc::new(Sound::Trim(
+                0.,
+                2.5,
+                Arc::new(Sound::Filter(
+                    filter::Filter::OnePole(1000.),
+                    osc(Func::Saw, freq),
+                )),
+            ))
+        };
+        let mix = Sound::Mix(vec![(0., note(220.)), (1.9, note(330.)), (4.2, note(440.))]);
+        assert_same(&mix, 1, 5.);
+        assert_same(&Sound::App(Func::Sin, vec![Arc::new(Sound::T)]), 1, 5.);
+    }
+
+    #[test]
+    fn zero_channels_is_an_error() {
+        let mut buffer = Buffer::default();
+        assert!(matches!(
+            render(&mut buffer, &Sound::T, 8000, 0, 1., 4),
+            Err(sink::Error::Sound(Error::NoChannels))
+        ));
+        assert!(buffer.samples.is_empty());
+    }
+
+    #[test]
+    fn stateful_sounds() {
+        let hat = Arc::new(Sound::Trim(
+            0.,
+            0.05,
+            Arc::new(Sound::Noise(noise::Color::White, 42)),
+        ));
+        let hats = Sound::Trigger(Arc::new(Sound::Concat(
+            (0..8)
+                .map(|_| (0.25, constant(Value::Sound(hat.clone()))))
+                .collect(),
+        )));
+        assert_same(&hats, 1, 2.);
+    }
+}
@@ -0,0 +1,473 @@
+use std::fmt;
+
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag},
+    character::complete::{alpha1, alphanumeric1, char, digit1, multispace1},
+    combinator::{all_consuming, cut, map, map_opt, map_res, opt, recognize, value, verify},
+    multi::{many0, many0_count, separated_list0},
+    number::complete::recognize_float,
+    sequence::{delimited, pair, preceded, separated_pair, terminated},
+    IResult,
+};
+
+use super::*;
+
+/// `名前 = 式` の並び．各式はそれより前の名前を参照できる．
+pub struct Program {
+    pub defs: Vec<(String, Expr)>,
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+impl std::error::Error for ParseError {}
+
+enum Raw<'a> {
+    Int(i64),
+    Float(f64),
+    Ident(&'a str),
+    Call(Box<Raw<'a>>, Vec<Raw<'a>>),
+    Lambda(Vec<(&'a str, ty::Ty)>, ty::Ty, Box<Raw<'a>>),
+}
+
+/// `input` をパースする．`locals` は外から与えるローカル変数の名前で，`Expr::Local` の先頭に並ぶ．
+pub fn parse(input: &str, locals: &[&str]) -> Result<Program, ParseError> {
+    let error = |offset: usize, message: String| {
+        let before = &input[..offset];
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before.rsplit('\n').next().unwrap().chars().count() + 1,
+            message,
+        }
+    };
+    let (_, raw) = all_consuming(parse_program)(input).map_err(|err| match err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => {
+            let message = match err.input.split_whitespace().next() {
+                Some(token) => format!("unexpected `{token}`"),
+                None => String::from("unexpected end of input"),
+            };
+            error(input.len() - err.input.len(), message)
+        }
+        nom::Err::Incomplete(_) => error(input.len(), String::from("unexpected end of input")),
+    })?;
+    let mut names: Vec<&str> = locals.to_vec();
+    let mut defs = Vec::new();
+    for (name, raw) in raw {
+        let expr = resolve(&raw, &names).map_err(|ident| {
+            // 識別子は `input` の一部なので，その位置から行と列を求める
+            let offset = ident.as_ptr() as usize - input.as_ptr() as usize;
+            error(offset, format!("`{ident}` is not defined"))
+        })?;
+        names.push(name);
+        defs.push((String::from(name), expr));
+    }
+    Ok(Program { defs })
+}
+
+fn resolve<'a: 'b, 'b>(raw: &Raw<'a>, names: &[&'b str]) -> Result<Expr, &'a str> {
+    match *raw {
+        Raw::Int(value) => Ok(Expr::Imm(Value::Int(value))),
+        Raw::Float(value) => Ok(Expr::Imm(Value::Float(value))),
+        Raw::Ident(ident) => match names.iter().rposition(|&name| name == ident) {
+            Some(pos) => Ok(Expr::Local(pos)),
+            None if ident == "t" => Ok(Expr::Imm(Value::Sound(Arc::new(Sound::T)))),
+            None if ident == "true" => Ok(Expr::Imm(Value::Bool(true))),
+            None if ident == "false" => Ok(Expr::Imm(Value::Bool(false))),
+            None => builtin(ident)
+                .map(|func| Expr::Imm(Value::Func(func)))
+                .ok_or(ident),
+        },
+        Raw::Call(ref func, ref args) => Ok(Expr::Call(
+            Box::new(resolve(func, names)?),
+            args.iter()
+                .map(|arg| resolve(arg, names))
+                .collect::<Result<_, _>>()?,
+        )),
+        Raw::Lambda(ref params, ref ret, ref body) => {
+            let names = names
+                .iter()
+                .copied()
+                .chain(params.iter().map(|&(name, _)| name))
+                .collect::<Vec<_>>();
+            Ok(Expr::Lambda(
+                params.iter().map(|(_, ty)| ty.clone()).collect(),
+                ret.clone(),
+                Arc::new(resolve(body, &names)?),
+            ))
+        }
+    }
+}
+
+const BUILTINS: &[(&str, Func)] = &[
+    ("sin", Func::Sin),
+    ("saw", Func::Saw),
+    ("square", Func::Square),
+    ("triangle", Func::Triangle),
+    ("add", Func::Add),
+    ("sub", Func::Sub),
+    ("mul", Func::Mul),
+    ("div", Func::Div),
+    ("add_int", Func::AddInt),
+    ("sub_int", Func::SubInt),
+    ("mul_int", Func::MulInt),
+    ("div_int", Func::DivInt),
+    ("rem_int", Func::RemInt),
+    ("to_float", Func::ToFloat),
+    ("floor", Func::Floor),
+    ("less", Func::Less),
+    ("less_eq", Func::LessEq),
+    ("equal", Func::Equal),
+    ("less_int", Func::LessInt),
+    ("less_eq_int", Func::LessEqInt),
+    ("equal_int", Func::EqualInt),
+    ("and", Func::And),
+    ("or", Func::Or),
+    ("not", Func::Not),
+    ("if", Func::If),
+    ("shift", Func::Shift),
+    ("stretch", Func::Stretch),
+    ("trim", Func::Trim),
+    ("adsr", Func::Adsr),
+    ("white_noise", Func::Noise(noise::Color::White)),
+    ("pink_noise", Func::Noise(noise::Color::Pink)),
+    ("brown_noise", Func::Noise(noise::Color::Brown)),
+    ("pan", Func::Pan),
+    ("mix_down", Func::MixDown),
+    ("add_frame", Func::AddFrame),
+    ("app", Func::App),
+    ("const", Func::Const),
+    ("integrate", Func::Integrate),
+    ("osc", Func::Osc),
+    ("one_pole", Func::OnePole),
+    ("lowpass", Func::Lowpass),
+    ("highpass", Func::Highpass),
+    ("bandpass", Func::Bandpass),
+    ("delay", Func::Delay),
+    ("comb", Func::Comb),
+    ("concat", Func::Concat),
+    ("flatten", Func::Flatten),
+    ("trigger", Func::Trigger),
+];
+
+pub(crate) fn builtin(name: &str) -> Option<Func> {
+    BUILTINS
+        .iter()
+        .find(|&&(builtin, _)| builtin == name)
+        .map(|(_, func)| func.clone())
+}
+
+pub(crate) fn builtin_name(func: &Func) -> Option<&'static str> {
+    BUILTINS
+        .iter()
+        .find(|(_, builtin)| match (builtin, func) {
+            (Func::Noise(a), Func::Noise(b)) => a == b,
+            (_, Func::Closure(_)) => false,
+            _ => std::mem::discriminant(builtin) == std::mem::discriminant(func),
+        })
+        .map(|&(name, _)| name)
+}
+
+impl Program {
+    /// 先頭から順に評価し，各定義の値を `locals` に続けて並べる．
+    pub fn eval(&self, mut locals: Vec<Value>) -> Result<Vec<Value>, Error> {
+        for (_, expr) in &self.defs {
+            let value = expr.eval(&locals)?;
+            locals.push(value);
+        }
+        Ok(locals)
+    }
+    pub fn check(&self, mut locals: Vec<ty::Ty>) -> Result<Vec<ty::Ty>, check::TypeError> {
+        for (_, expr) in &self.defs {
+            let ty = expr.check(&locals)?;
+            locals.push(ty);
+        }
+        Ok(locals)
+    }
+}
+
+fn space(input: &str) -> IResult<&str, ()> {
+    value(
+        (),
+        many0_count(alt((
+            multispace1,
+            recognize(pair(char('#'), opt(is_not("\n")))),
+        ))),
+    )(input)
+}
+
+fn token<'a, O>(
+    parser: impl FnMut(&'a str) -> IResult<&'a str, O>,
+) -> impl FnMut(&'a str) -> IResult<&'a str, O> {
+    terminated(parser, space)
+}
+
+fn ident(input: &str) -> IResult<&str, &str> {
+    token(recognize(pair(
+        alt((alpha1, tag("_"))),
+        many0_count(alt((alphanumeric1, tag("_")))),
+    )))(input)
+}
+
+const KEYWORDS: &[&str] = &["fn"];
+
+fn keyword<'a>(word: &'static str) -> impl FnMut(&'a str) -> IResult<&'a str, &'a str> {
+    verify(ident, move |ident: &str| ident == word)
+}
+
+fn name(input: &str) -> IResult<&str, &str> {
+    verify(ident, |ident: &str| !KEYWORDS.contains(&ident))(input)
+}
+
+/// `.` も指数もなければ整数，あれば浮動小数点数．
+fn number(input: &str) -> IResult<&str, Raw<'_>> {
+    map_res(token(recognize_float), |num: &str| {
+        if num.contains(['.', 'e', 'E']) {
+            num.parse().map(Raw::Float).map_err(|_| ())
+        } else {
+            num.parse().map(Raw::Int).map_err(|_| ())
+        }
+    })(input)
+}
+
+fn parse_ty(input: &str) -> IResult<&str, ty::Ty> {
+    let func = preceded(
+        keyword("fn"),
+        cut(pair(
+            delimited(
+                token(char('(')),
+                separated_list0(token(char(',')), parse_ty),
+                token(char(')')),
+            ),
+            preceded(token(tag("->")), parse_ty),
+        )),
+    );
+    let app = map_opt(
+        pair(
+            ident,
+            opt(delimited(
+                token(char('[')),
+                cut(parse_ty),
+                cut(token(char(']'))),
+            )),
+        ),
+        |(name, arg)| match (name, arg) {
+            ("Int", None) => Some(ty::Ty::int()),
+            ("Float", None) => Some(ty::Ty::float()),
+            ("Bool", None) => Some(ty::Ty::bool()),
+            ("Sound", Some(ty)) => Some(ty::Ty::sound(ty)),
+            _ => None,
+        },
+    );
+    // `Frame[2]` のように，フレームはチャンネル数をとる
+    let frame = preceded(
+        keyword("Frame"),
+        delimited(
+            token(char('[')),
+            cut(map_res(token(digit1), str::parse)),
+            cut(token(char(']'))),
+        ),
+    );
+    alt((
+        map(func, |(params, ret)| ty::Ty::func(ret, params)),
+        map(frame, ty::Ty::frame),
+        app,
+    ))(input)
+}
+
+fn parse_program(input: &str) -> IResult<&str, Vec<(&str, Raw<'_>)>> {
+    // 定義：名前 = 式，続く ; は省略できる
+    let def = terminated(
+        separated_pair(name, token(char('=')), cut(parse_expr)),
+        opt(token(char(';'))),
+    );
+    preceded(space, many0(def))(input)
+}
+
+fn parse_expr(input: &str) -> IResult<&str, Raw<'_>> {
+    let lambda = preceded(
+        keyword("fn"),
+        cut(map(
+            pair(
+                pair(
+                    delimited(
+                        token(char('(')),
+                        separated_list0(
+                            token(char(',')),
+                            separated_pair(name, token(char(':')), cut(parse_ty)),
+                        ),
+                        token(char(')')),
+                    ),
+                    preceded(token(tag("->")), parse_ty),
+                ),
+                delimited(token(char('{')), parse_expr, token(char('}'))),
+            ),
+            |((params, ret), body)| Raw::Lambda(params, ret, Box::new(body)),
+        )),
+    );
+    let primary = alt((
+        number,
+        lambda,
+        map(name, Raw::Ident),
+        delimited(token(char('(')), cut(parse_expr), cut(token(char(')')))),
+    ));
+    let call = delimited(
+        token(char('(')),
+        separated_list0(token(char(',')), parse_expr),
+        cut(token(char(')'))),
+    );
+    map(pair(primary, many0(call)), |(func, calls)| {
+        calls
+            .into_iter()
+            .fold(func, |func, args| Raw::Call(Box::new(func), args))
+    })(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(input: &str) -> String {
+        parse(input, &[]).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn programs() {
+        let program = parse(
+            "# コメント
+            x = 2.0; y = mul(x, 3.5)
+            tone = osc(saw, const(y))  # 行末のコメント
+            out = lowpass(800.0, 0.7, tone)",
+            &["gain"],
+        )
+        .unwrap();
+        let names: Vec<_> = program.defs.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["x", "y", "tone", "out"]);
+        assert!(matches!(program.defs[1].1, Expr::Call(_, ref args) if args.len() == 2));
+
+        let tys = program.check(vec![ty::Ty::float()]).unwrap();
+        assert_eq!(tys[2], ty::Ty::float());
+        assert_eq!(tys[4], ty::Ty::sound(ty::Ty::float()));
+        let values = program.eval(vec![Value::Float(1.)]).unwrap();
+        assert_eq!(values[2].as_float(), Some(&7.));
+    }
+
+    #[test]
+    fn names_shadow_builtins() {
+        let program = parse("sin = 1.0\nx = add(sin, t)", &[]).unwrap();
+        assert!(matches!(program.defs[0].1, Expr::Imm(Value::Float(_))));
+        assert!(matches!(
+            program.defs[1].1,
+            Expr::Call(_, ref args) if matches!(args[0], Expr::Local(0))
+        ));
+    }
+
+    #[test]
+    fn lambdas() {
+        let program = parse(
+            "k = 2.0
+            scale = fn(x: Float) -> Float { mul(k, x) }
+            twice = fn(f: fn(Float) -> Float, x: Float) -> Float { f(f(x)) }
+            y = twice(scale, 3.0)",
+            &[],
+        )
+        .unwrap();
+        let tys = program.check(vec![]).unwrap();
+        let float = ty::Ty::float();
+        let unary = ty::Ty::func(float.clone(), vec![float.clone()]);
+        assert_eq!(tys[1], unary);
+        assert_eq!(tys[2], ty::Ty::func(float.clone(), vec![unary, float]));
+        let values = program.eval(vec![]).unwrap();
+        assert_eq!(values[3].as_float(), Some(&12.));
+
+        // `app` に渡したクロージャはサンプルごとに呼ばれる
+        let program = parse(
+            "k = 2.0\nout = app(fn(x: Float) -> Float { mul(k, x) }, t)",
+            &[],
+        )
+        .unwrap();
+        let out = program.eval(vec![]).unwrap().pop().unwrap();
+        let values = out.into_sound().unwrap().sample(4., 3).unwrap();
+        let values: Vec<_> = values
+            .into_iter()
+            .map(|x| x.into_float().unwrap())
+            .collect();
+        assert_eq!(values, [0., 0.5, 1.]);
+
+        for (patch, parses, checks) in [
+            ("f = fn(x: Float) -> Int { x }", true, false),
+            ("f = fn(x: Float) -> Float { x }\ny = f(true)", true, false),
+            (
+                "f = fn(s: Sound[Float]) -> Sound[Float] { app(sin, s) }",
+                true,
+                true,
+            ),
+            ("f = fn(x: Frame[2]) -> Float { mix_down(x) }", true, true),
+            (
+                "f = fn(x: Frame[2]) -> Frame[2] { add_frame(x, pan(1.0, 0.0)) }",
+                true,
+                true,
+            ),
+            (
+                "f = fn(x: Frame[3]) -> Frame[3] { add_frame(x, pan(1.0, 0.0)) }",
+                true,
+                false,
+            ),
+            ("f = fn(x: Frame) -> Float { mix_down(x) }", false, false),
+            ("f = fn(x: Foo) -> Float { x }", false, false),
+            ("f = fn(x: Float) -> Float { y }", false, false),
+            ("f = fn(x: Float) Float { x }", false, false),
+        ] {
+            let program = parse(patch, &[]);
+            assert_eq!(program.is_ok(), parses, "{patch}");
+            if let Ok(program) = program {
+                assert_eq!(program.check(vec![]).is_ok(), checks, "{patch}");
+            }
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(error("x = sin(1"), "1:10: unexpected end of input");
+        assert_eq!(error("x = add(1, )"), "1:10: unexpected `,`");
+        assert_eq!(error("x = 1\ny = mul(x, 2) z"), "2:15: unexpected `z`");
+        assert_eq!(
+            error("x = app(sin, y)\nz = add(1.0, 2.0)\nw = 1.0"),
+            "1:14: `y` is not defined"
+        );
+        assert_eq!(error("x = 1.0\ny = add(x, z)"), "2:12: `z` is not defined");
+    }
+
+    fn eval(input: &str) -> Value {
+        let program = parse(input, &[]).unwrap();
+        program.check(vec![]).unwrap();
+        program.eval(vec![]).unwrap().pop().unwrap()
+    }
+
+    #[test]
+    fn number_literals() {
+        assert!(matches!(eval("x = add_int(1, 2)"), Value::Int(3)));
+        assert!(matches!(eval("x = sub_int(0, -4)"), Value::Int(4)));
+        assert!(matches!(eval("x = add(1.5, 2.)"), Value::Float(x) if x == 3.5));
+        assert!(matches!(eval("x = mul(1e3, 2.0)"), Value::Float(x) if x == 2000.));
+        assert!(matches!(eval("x = white_noise(3)"), Value::Sound(_)));
+        let program = parse("x = add(1, 2)", &[]).unwrap();
+        assert!(program.check(vec![]).is_err());
+        assert!(parse("x = 99999999999999999999", &[]).is_err());
+    }
+
+    #[test]
+    fn fn_is_reserved() {
+        assert!(matches!(parse("fn = 1", &[]), Err(err) if err.column == 1));
+        assert!(parse("f = fn(fn: Int) -> Int { fn }", &[]).is_err());
+        assert!(parse("f = fn(x: Int) -> Int { x }", &[]).is_ok());
+    }
+}
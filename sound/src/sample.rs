@@ -0,0 +1,164 @@
+use std::fmt;
+
+use super::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Interp {
+    Linear,
+    Cubic,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Loop {
+    Off,
+    Whole,
+    /// 先頭から鳴らし，[開始, 終了) [s] の区間を繰り返す．
+    Range(f64, f64),
+}
+
+/// 録音済みのサンプル列．時刻 0 で先頭のサンプルを鳴らし，0 より前は無音になる．
+pub struct Sample {
+    rate: f64,
+    channels: usize,
+    data: Vec<f64>,
+    pub interp: Interp,
+    pub looping: Loop,
+}
+
+impl fmt::Debug for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sample")
+            .field("rate", &self.rate)
+            .field("channels", &self.channels)
+            .field("frames", &self.frames())
+            .field("interp", &self.interp)
+            .field("looping", &self.looping)
+            .finish()
+    }
+}
+
+impl Sample {
+    pub fn new(rate: f64, channels: usize, data: Vec<f64>) -> Sample {
+        Sample {
+            rate,
+            channels,
+            data,
+            interp: Interp::Linear,
+            looping: Loop::Off,
+        }
+    }
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+    pub fn channel(&self, channel: usize) -> Option<Vec<f64>> {
+        (channel < self.channels).then(|| {
+            self.data
+                .iter()
+                .skip(channel)
+                .step_by(self.channels)
+                .copied()
+                .collect()
+        })
+    }
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+    pub fn frames(&self) -> usize {
+        self.data.len() / self.channels
+    }
+    pub fn end(&self) -> Option<f64> {
+        match self.looping {
+            Loop::Off => Some(self.frames() as f64 / self.rate),
+            Loop::Whole | Loop::Range(..) => None,
+        }
+    }
+    fn index(&self, i: i64) -> Option<usize> {
+        let frames = self.frames() as i64;
+        let (start, end) = match self.looping {
+            Loop::Off => (frames, frames),
+            Loop::Whole => (0, frames),
+            Loop::Range(start, end) => (
+                ((start * self.rate).round() as i64).clamp(0, frames),
+                ((end * self.rate).round() as i64).clamp(0, frames),
+            ),
+        };
+        let i = match i {
+            i if i < 0 => return None,
+            i if i >= end && end > start => start + (i - start).rem_euclid(end - start),
+            i => i,
+        };
+        (i < frames).then_some(i as usize)
+    }
+    fn get(&self, i: i64, channel: usize) -> f64 {
+        self.index(i)
+            .map_or(0., |i| self.data[i * self.channels + channel])
+    }
+    pub fn at(&self, t: f64) -> Value {
+        let pos = t * self.rate;
+        let i = pos.floor();
+        let x = pos - i;
+        let i = i as i64;
+        let value = |channel| {
+            let p = |k| self.get(i + k, channel);
+            match self.interp {
+                Interp::Linear => p(0) + x * (p(1) - p(0)),
+                Interp::Cubic => {
+                    let (p0, p1, p2, p3) = (p(-1), p(0), p(1), p(2));
+                    p1 + 0.5
+                        * x
+                        * (p2 - p0
+                            + x * (2. * p0 - 5. * p1 + 4. * p2 - p3
+                                + x * (3. * (p1 - p2) + p3 - p0)))
+                }
+            }
+        };
+        match self.channels {
+            1 => Value::Float(value(0)),
+            n => Value::Frame((0..n).map(value).collect()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floats(sample: &Sample, times: &[f64]) -> Vec<f64> {
+        times
+            .iter()
+            .map(|&t| sample.at(t).into_float().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn interpolation() {
+        let mut sample = Sample::new(4., 1, vec![0., 1., 0., -1.]);
+        assert_eq!(sample.end(), Some(1.));
+        assert_eq!(floats(&sample, &[-0.25, 0.125, 0.5, 1.]), [0., 0.5, 0., 0.]);
+        sample.interp = Interp::Cubic;
+        assert_eq!(floats(&sample, &[0.25, 0.5]), [1., 0.]);
+        assert!((floats(&sample, &[0.125])[0] - 0.5625).abs() < 1e-9);
+    }
+
+    #[test]
+    fn looping() {
+        let mut sample = Sample::new(4., 1, vec![1., 2., 3., 4.]);
+        sample.looping = Loop::Whole;
+        assert_eq!(sample.end(), None);
+        assert_eq!(floats(&sample, &[1., 1.75, 2.25]), [1., 4., 2.]);
+        sample.looping = Loop::Range(0.25, 0.75);
+        let times: Vec<_> = (0..8).map(|i| i as f64 / 4.).collect();
+        assert_eq!(floats(&sample, &times), [1., 2., 3., 2., 3., 2., 3., 2.]);
+        sample.looping = Loop::Off;
+        assert_eq!(floats(&sample, &[1.]), [0.]);
+    }
+
+    #[test]
+    fn frames() {
+        let sample = Sample::new(2., 2, vec![1., -1., 2., -2.]);
+        assert_eq!(sample.frames(), 2);
+        assert_eq!(sample.channel(1), Some(vec![-1., -2.]));
+        assert_eq!(sample.channel(2), None);
+        assert!(matches!(sample.at(0.5), Value::Frame(frame) if frame[..] == [2., -2.]));
+    }
+}
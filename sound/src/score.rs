@@ -0,0 +1,183 @@
+use super::*;
+
+/// `A4`，`C#3`，`Bb-1` のような音名の MIDI ノート番号．A4 が 69 になる．
+pub fn midi(name: &str) -> Option<i32> {
+    let mut chars = name.chars();
+    let class = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let octave = rest.trim_start_matches(['#', 'b']);
+    let accidental = rest[..rest.len() - octave.len()]
+        .chars()
+        .map(|c| if c == '#' { 1 } else { -1 })
+        .sum::<i32>();
+    let octave: i32 = octave.parse().ok()?;
+    Some(12 * (octave + 1) + class + accidental)
+}
+
+pub fn freq(midi: f64) -> f64 {
+    440. * 2f64.powf((midi - 69.) / 12.)
+}
+
+pub fn note(name: &str) -> Option<f64> {
+    midi(name).map(|midi| freq(midi as f64))
+}
+
+pub fn cents(freq: f64, cents: f64) -> f64 {
+    freq * 2f64.powf(cents / 1200.)
+}
+
+#[derive(Clone, Debug)]
+pub struct Tempo {
+    /// (拍, BPM) を拍の順に並べる．先頭は 0 拍目．
+    changes: Vec<(f64, f64)>,
+}
+
+impl Tempo {
+    pub fn new(bpm: f64) -> Tempo {
+        Tempo {
+            changes: vec![(0., bpm)],
+        }
+    }
+    /// `beat` 拍目から `bpm` にする．負の拍は 0 拍目とみなす．
+    pub fn set(&mut self, beat: f64, bpm: f64) {
+        let beat = beat.max(0.);
+        let i = self.changes.partition_point(|&(start, _)| start < beat);
+        match self.changes.get_mut(i) {
+            Some(change) if change.0 == beat => change.1 = bpm,
+            _ => self.changes.insert(i, (beat, bpm)),
+        }
+    }
+    /// `beat` 拍目の時刻 [s]．0 拍目より前は最初のテンポのままとみなす．
+    pub fn seconds(&self, beat: f64) -> f64 {
+        let mut seconds = 0.;
+        for (i, &(start, bpm)) in self.changes.iter().enumerate() {
+            match self.changes.get(i + 1) {
+                Some(&(end, _)) if beat >= end => seconds += (end - start) * 60. / bpm,
+                _ => return seconds + (beat - start) * 60. / bpm,
+            }
+        }
+        seconds
+    }
+    pub fn beats(&self, seconds: f64) -> f64 {
+        let mut elapsed = 0.;
+        for (i, &(start, bpm)) in self.changes.iter().enumerate() {
+            match self.changes.get(i + 1) {
+                Some(&(end, _)) if seconds >= elapsed + (end - start) * 60. / bpm => {
+                    elapsed += (end - start) * 60. / bpm
+                }
+                _ => return start + (seconds - elapsed) * bpm / 60.,
+            }
+        }
+        0.
+    }
+}
+
+/// 楽譜の中の 1 音．`start` と `duration` は拍で数える．
+#[derive(Clone, Debug)]
+pub struct Event {
+    pub start: f64,
+    pub duration: f64,
+    /// 長さ [s] と `params` を受け取って音を返す関数．
+    pub instrument: Func,
+    pub params: Vec<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Score {
+    pub tempo: Tempo,
+    pub events: Vec<Event>,
+}
+
+impl Score {
+    pub fn sound(&self) -> Result<Sound, Error> {
+        let mut sounds = Vec::with_capacity(self.events.len());
+        for event in &self.events {
+            let start = self.tempo.seconds(event.start);
+            let duration = self.tempo.seconds(event.start + event.duration) - start;
+            let args = std::iter::once(Value::Float(duration))
+                .chain(event.params.iter().cloned())
+                .collect();
+            match event.instrument.call(args)? {
+                Value::Sound(sound) => sounds.push((start, sound)),
+                found => {
+                    return Err(Error::Input {
+                        node: "Score",
+                        expected: ty::Kind::Sound,
+                        found,
+                    })
+                }
+            }
+        }
+        Ok(Sound::Mix(sounds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tempo_changes() {
+        let mut tempo = Tempo::new(120.);
+        tempo.set(4., 60.);
+        for (beat, seconds) in [(-1., -0.5), (0., 0.), (2., 1.), (4., 2.), (5., 3.)] {
+            assert_eq!(tempo.seconds(beat), seconds);
+            assert_eq!(tempo.beats(seconds), beat);
+        }
+    }
+
+    #[test]
+    fn negative_beat_sets_first_tempo() {
+        let mut tempo = Tempo::new(120.);
+        tempo.set(-4., 60.);
+        assert_eq!(tempo.seconds(0.), 0.);
+        assert_eq!(tempo.seconds(1.), 1.);
+        assert_eq!(tempo.changes, [(0., 60.)]);
+    }
+
+    #[test]
+    fn note_names() {
+        assert_eq!(midi("A4"), Some(69));
+        assert_eq!(midi("C#3"), midi("Db3"));
+        assert_eq!(midi("Bb-1"), Some(10));
+        assert_eq!(midi("H2"), None);
+        assert_eq!(note("A5"), Some(880.));
+        assert_eq!(freq(57.), 220.);
+        assert!((cents(440., -1200.) - 220.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn events_are_mixed() {
+        let src = "hold = fn(len: Float, x: Float) -> Sound[Float] { trim(0.0, len, const(x)) }";
+        let program = crate::parse::parse(src, &[]).unwrap();
+        let hold = program.eval(vec![]).unwrap()[0].as_func().unwrap().clone();
+        let event = |start, x| Event {
+            start,
+            duration: 1.,
+            instrument: hold.clone(),
+            params: vec![Value::Float(x)],
+        };
+        let score = Score {
+            tempo: Tempo::new(120.),
+            events: vec![event(0., 1.), event(0.5, 2.)],
+        };
+        let sound = score.sound().unwrap();
+        assert_eq!(sound.end(), Some(0.75));
+        let values: Vec<_> = sound
+            .sample(8., 8)
+            .unwrap()
+            .into_iter()
+            .map(|value| value.into_float().unwrap())
+            .collect();
+        assert_eq!(values, [1., 1., 3., 3., 2., 2., 0., 0.]);
+    }
+}
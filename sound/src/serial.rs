@@ -0,0 +1,491 @@
+//! 音のグラフを読み書きできるテキストにする．
+//!
+//! ```text
+//! sound 1
+//! %0 = t
+//! %1 = const float 6.2832
+//! %2 = app mul %1 %0
+//! %3 = app sin %2
+//! out %3
+//! ```
+//!
+//! 1 行目は版で，続く各行で 1 つのノードに名前を付ける．
+//! 同じノードを複数の場所から参照すれば，読み込んだ後も共有される．
+//! `#` から行末まではコメントになる．
+
+use std::{collections::HashMap, fmt::Write};
+
+use super::*;
+use crate::{
+    envelope::{Curve, Shape},
+    filter::{BiquadKind, Filter},
+    parse::ParseError,
+};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Unsupported(&'static str),
+}
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SaveError::Unsupported(what) => write!(f, "{what} cannot be saved"),
+        }
+    }
+}
+
+pub fn save(sound: &Sound) -> Result<String, SaveError> {
+    let mut saver = Saver {
+        out: format!("sound {VERSION}\n"),
+        ids: HashMap::new(),
+        next: 0,
+    };
+    let root = saver.node(sound)?;
+    writeln!(saver.out, "out {root}").unwrap();
+    Ok(saver.out)
+}
+
+struct Saver {
+    out: String,
+    ids: HashMap<*const Sound, String>,
+    next: usize,
+}
+
+impl Saver {
+    /// 共有されたノードは一度だけ書き出す．
+    fn shared(&mut self, sound: &Arc<Sound>) -> Result<String, SaveError> {
+        if let Some(id) = self.ids.get(&Arc::as_ptr(sound)) {
+            return Ok(id.clone());
+        }
+        let id = self.node(sound)?;
+        self.ids.insert(Arc::as_ptr(sound), id.clone());
+        Ok(id)
+    }
+    fn node(&mut self, sound: &Sound) -> Result<String, SaveError> {
+        let mut line = String::new();
+        match sound {
+            Sound::T => line.push('t'),
+            Sound::Const(value) => {
+                line.push_str("const ");
+                self.value(&mut line, value)?;
+            }
+            Sound::App(func, sounds) => {
+                write!(line, "app {}", func_name(func)?).unwrap();
+                for sound in sounds {
+                    write!(line, " {}", self.shared(sound)?).unwrap();
+                }
+            }
+            Sound::Integrate(sound) => write!(line, "integrate {}", self.shared(sound)?).unwrap(),
+            Sound::Filter(filter, sound) => {
+                line.push_str("filter ");
+                match *filter {
+                    Filter::OnePole(cutoff) => write!(line, "one_pole {cutoff:?}"),
+                    Filter::Biquad(kind, freq, q) => {
+                        let kind = match kind {
+                            BiquadKind::Lowpass => "lowpass",
+                            BiquadKind::Highpass => "highpass",
+                            BiquadKind::Bandpass => "bandpass",
+                        };
+                        write!(line, "{kind} {freq:?} {q:?}")
+                    }
+                    Filter::Delay(delay) => write!(line, "delay {delay:?}"),
+                    Filter::Comb(delay, feedback) => write!(line, "comb {delay:?} {feedback:?}"),
+                    Filter::Convolve(_) => {
+                        return Err(SaveError::Unsupported("an impulse response"))
+                    }
+                    Filter::Reverb(time, damping, wet) => {
+                        write!(line, "reverb {time:?} {damping:?} {wet:?}")
+                    }
+                }
+                .unwrap();
+                write!(line, " {}", self.shared(sound)?).unwrap();
+            }
+            Sound::Shift(offset, sound) => {
+                write!(line, "shift {offset:?} {}", self.shared(sound)?).unwrap()
+            }
+            Sound::Stretch(scale, sound) => {
+                write!(line, "stretch {scale:?} {}", self.shared(sound)?).unwrap()
+            }
+            Sound::Trim(from, to, sound) => {
+                write!(line, "trim {from:?} {to:?} {}", self.shared(sound)?).unwrap()
+            }
+            Sound::Concat(sounds) | Sound::Mix(sounds) => {
+                line.push_str(match sound {
+                    Sound::Concat(_) => "concat",
+                    _ => "mix",
+                });
+                for (time, sound) in sounds {
+                    write!(line, " {time:?} {}", self.shared(sound)?).unwrap();
+                }
+            }
+            Sound::Merge(sounds) => {
+                line.push_str("merge");
+                for sound in sounds {
+                    write!(line, " {}", self.shared(sound)?).unwrap();
+                }
+            }
+            Sound::Channel(i, sound) => {
+                write!(line, "channel {i} {}", self.shared(sound)?).unwrap()
+            }
+            Sound::Flatten(sound) => write!(line, "flatten {}", self.shared(sound)?).unwrap(),
+            Sound::Trigger(sound) => write!(line, "trigger {}", self.shared(sound)?).unwrap(),
+            Sound::Sample(_) => return Err(SaveError::Unsupported("a sample buffer")),
+            Sound::Noise(color, seed) => {
+                let color = match color {
+                    noise::Color::White => "white",
+                    noise::Color::Pink => "pink",
+                    noise::Color::Brown => "brown",
+                };
+                write!(line, "noise {color} {seed}").unwrap();
+            }
+            Sound::Adsr(adsr, gate) => write!(
+                line,
+                "adsr {:?} {:?} {:?} {:?} {}",
+                adsr.attack,
+                adsr.decay,
+                adsr.sustain,
+                adsr.release,
+                self.shared(gate)?
+            )
+            .unwrap(),
+            Sound::Curve(curve) => {
+                line.push_str(match curve.shape() {
+                    Shape::Linear => "curve linear",
+                    Shape::Exponential => "curve exponential",
+                });
+                for (time, value) in curve.points() {
+                    write!(line, " {time:?} {value:?}").unwrap();
+                }
+            }
+        }
+        let id = format!("%{}", self.next);
+        self.next += 1;
+        writeln!(self.out, "{id} = {line}").unwrap();
+        Ok(id)
+    }
+    fn value(&mut self, line: &mut String, value: &Value) -> Result<(), SaveError> {
+        match value {
+            Value::Int(x) => write!(line, "int {x}").unwrap(),
+            Value::Float(x) => write!(line, "float {x:?}").unwrap(),
+            Value::Bool(x) => write!(line, "bool {x}").unwrap(),
+            Value::Frame(xs) => {
+                line.push_str("frame");
+                for x in xs.iter() {
+                    write!(line, " {x:?}").unwrap();
+                }
+            }
+            Value::Func(func) => write!(line, "func {}", func_name(func)?).unwrap(),
+            Value::Sound(sound) => write!(line, "sound {}", self.shared(sound)?).unwrap(),
+        }
+        Ok(())
+    }
+}
+
+fn func_name(func: &Func) -> Result<&'static str, SaveError> {
+    parse::builtin_name(func).ok_or(SaveError::Unsupported("a closure"))
+}
+
+pub fn load(input: &str) -> Result<Arc<Sound>, ParseError> {
+    let mut nodes: HashMap<&str, Arc<Sound>> = HashMap::new();
+    let mut version = None;
+    let mut out = None;
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let mut tokens = Tokens {
+            line,
+            line_no: line_no + 1,
+            tokens: line
+                .split_whitespace()
+                .map(|token| (token.as_ptr() as usize - line.as_ptr() as usize, token))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .peekable(),
+        };
+        let Some((column, first)) = tokens.tokens.peek().copied() else {
+            continue;
+        };
+        if out.is_some() {
+            return Err(tokens.error(column, String::from("unexpected line after `out`")));
+        }
+        if version.is_none() {
+            tokens.keyword("sound")?;
+            let (column, found) = tokens.next("a version")?;
+            match found.parse::<u32>() {
+                Ok(VERSION) => version = Some(VERSION),
+                _ => return Err(tokens.error(column, format!("unsupported version `{found}`"))),
+            }
+            tokens.end()?;
+            continue;
+        }
+        if first == "out" {
+            tokens.next("`out`")?;
+            out = Some(tokens.sound(&nodes)?);
+            tokens.end()?;
+            continue;
+        }
+        let (column, id) = tokens.next("a node name")?;
+        if !id.starts_with('%') {
+            return Err(tokens.error(column, format!("expected a node name, found `{id}`")));
+        }
+        if nodes.contains_key(id) {
+            return Err(tokens.error(column, format!("`{id}` is already defined")));
+        }
+        tokens.keyword("=")?;
+        let sound = tokens.node(&nodes)?;
+        tokens.end()?;
+        nodes.insert(id, Arc::new(sound));
+    }
+    if let Some(sound) = out {
+        return Ok(sound);
+    }
+    Err(ParseError {
+        line: input.lines().count() + 1,
+        column: 1,
+        message: String::from(if version.is_none() {
+            "missing `sound` header"
+        } else {
+            "missing `out`"
+        }),
+    })
+}
+
+struct Tokens<'a> {
+    line: &'a str,
+    line_no: usize,
+    tokens: std::iter::Peekable<std::vec::IntoIter<(usize, &'a str)>>,
+}
+
+impl<'a> Tokens<'a> {
+    fn error(&self, offset: usize, message: String) -> ParseError {
+        ParseError {
+            line: self.line_no,
+            column: self.line[..offset].chars().count() + 1,
+            message,
+        }
+    }
+    fn next(&mut self, expected: &str) -> Result<(usize, &'a str), ParseError> {
+        match self.tokens.next() {
+            Some(token) => Ok(token),
+            None => Err(self.error(self.line.len(), format!("expected {expected}"))),
+        }
+    }
+    fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.next(&format!("`{keyword}`"))? {
+            (_, found) if found == keyword => Ok(()),
+            (column, found) => {
+                Err(self.error(column, format!("expected `{keyword}`, found `{found}`")))
+            }
+        }
+    }
+    fn end(&mut self) -> Result<(), ParseError> {
+        match self.tokens.next() {
+            None => Ok(()),
+            Some((column, found)) => Err(self.error(column, format!("unexpected `{found}`"))),
+        }
+    }
+    fn parse<T: std::str::FromStr>(&mut self, expected: &str) -> Result<T, ParseError> {
+        let (column, found) = self.next(expected)?;
+        found
+            .parse()
+            .map_err(|_| self.error(column, format!("expected {expected}, found `{found}`")))
+    }
+    fn float(&mut self) -> Result<f64, ParseError> {
+        self.parse("a number")
+    }
+    fn sound(&mut self, nodes: &HashMap<&str, Arc<Sound>>) -> Result<Arc<Sound>, ParseError> {
+        let (column, id) = self.next("a node name")?;
+        match nodes.get(id) {
+            Some(sound) => Ok(sound.clone()),
+            None => Err(self.error(column, format!("`{id}` is not defined"))),
+        }
+    }
+    fn sounds(&mut self, nodes: &HashMap<&str, Arc<Sound>>) -> Result<Vec<Arc<Sound>>, ParseError> {
+        let mut sounds = Vec::new();
+        while self.tokens.peek().is_some() {
+            sounds.push(self.sound(nodes)?);
+        }
+        Ok(sounds)
+    }
+    fn timed(
+        &mut self,
+        nodes: &HashMap<&str, Arc<Sound>>,
+    ) -> Result<Vec<(f64, Arc<Sound>)>, ParseError> {
+        let mut sounds = Vec::new();
+        while self.tokens.peek().is_some() {
+            sounds.push((self.float()?, self.sound(nodes)?));
+        }
+        Ok(sounds)
+    }
+    fn func(&mut self) -> Result<Func, ParseError> {
+        let (column, name) = self.next("a builtin name")?;
+        parse::builtin(name).ok_or_else(|| self.error(column, format!("unknown builtin `{name}`")))
+    }
+    fn value(&mut self, nodes: &HashMap<&str, Arc<Sound>>) -> Result<Value, ParseError> {
+        let (column, kind) = self.next("a value")?;
+        Ok(match kind {
+            "int" => Value::Int(self.parse("an integer")?),
+            "float" => Value::Float(self.float()?),
+            "bool" => Value::Bool(self.parse("`true` or `false`")?),
+            "frame" => {
+                let mut frame = Vec::new();
+                while self.tokens.peek().is_some() {
+                    frame.push(self.float()?);
+                }
+                Value::Frame(frame.into())
+            }
+            "func" => Value::Func(self.func()?),
+            "sound" => Value::Sound(self.sound(nodes)?),
+            _ => return Err(self.error(column, format!("unknown value kind `{kind}`"))),
+        })
+    }
+    fn node(&mut self, nodes: &HashMap<&str, Arc<Sound>>) -> Result<Sound, ParseError> {
+        let (column, kind) = self.next("a node")?;
+        Ok(match kind {
+            "t" => Sound::T,
+            "const" => Sound::Const(Box::new(self.value(nodes)?)),
+            "app" => Sound::App(self.func()?, self.sounds(nodes)?),
+            "integrate" => Sound::Integrate(self.sound(nodes)?),
+            "filter" => {
+                let (column, kind) = self.next("a filter")?;
+                let filter = match kind {
+                    "one_pole" => Filter::OnePole(self.float()?),
+                    "lowpass" | "highpass" | "bandpass" => {
+                        let kind = match kind {
+                            "lowpass" => BiquadKind::Lowpass,
+                            "highpass" => BiquadKind::Highpass,
+                            _ => BiquadKind::Bandpass,
+                        };
+                        Filter::Biquad(kind, self.float()?, self.float()?)
+                    }
+                    "delay" => Filter::Delay(self.float()?),
+                    "comb" => Filter::Comb(self.float()?, self.float()?),
+                    "reverb" => Filter::Reverb(self.float()?, self.float()?, self.float()?),
+                    _ => return Err(self.error(column, format!("unknown filter `{kind}`"))),
+                };
+                Sound::Filter(filter, self.sound(nodes)?)
+            }
+            "shift" => Sound::Shift(self.float()?, self.sound(nodes)?),
+            "stretch" => Sound::stretch(self.float()?, self.sound(nodes)?)
+                .map_err(|err| self.error(column, err.to_string()))?,
+            "trim" => Sound::trim(self.float()?, self.float()?, self.sound(nodes)?)
+                .map_err(|err| self.error(column, err.to_string()))?,
+            "concat" => Sound::concat(self.timed(nodes)?)
+                .map_err(|err| self.error(column, err.to_string()))?,
+            "mix" => Sound::Mix(self.timed(nodes)?),
+            "merge" => Sound::Merge(self.sounds(nodes)?),
+            "channel" => Sound::Channel(self.parse("a channel")?, self.sound(nodes)?),
+            "flatten" => Sound::Flatten(self.sound(nodes)?),
+            "trigger" => Sound::Trigger(self.sound(nodes)?),
+            "noise" => {
+                let (column, color) = self.next("a color")?;
+                let color = match color {
+                    "white" => noise::Color::White,
+                    "pink" => noise::Color::Pink,
+                    "brown" => noise::Color::Brown,
+                    _ => return Err(self.error(column, format!("unknown noise `{color}`"))),
+                };
+                Sound::Noise(color, self.parse("a seed")?)
+            }
+            "adsr" => Sound::Adsr(
+                envelope::Adsr {
+                    attack: self.float()?,
+                    decay: self.float()?,
+                    sustain: self.float()?,
+                    release: self.float()?,
+                },
+                self.sound(nodes)?,
+            ),
+            "curve" => {
+                let (column, shape) = self.next("a shape")?;
+                let shape = match shape {
+                    "linear" => Shape::Linear,
+                    "exponential" => Shape::Exponential,
+                    _ => return Err(self.error(column, format!("unknown shape `{shape}`"))),
+                };
+                let mut points = Vec::new();
+                while self.tokens.peek().is_some() {
+                    points.push((self.float()?, self.float()?));
+                }
+                Sound::Curve(Arc::new(Curve::new(points, shape)))
+            }
+            _ => return Err(self.error(column, format!("unknown node `{kind}`"))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "
+        vibrato = app(mul, const(0.02), osc(sin, const(5.0)))
+        env = adsr(0.01, 0.1, 0.6, 0.3, app(less, t, const(0.4)))
+        tone = app(mul, env, app(sin, app(mul, const(2764.6), app(add, t, vibrato))))
+        out = trim(0.0, 1.0, app(mix_down, app(pan, tone, const(-0.5))))
+    ";
+
+    #[test]
+    fn round_trip() {
+        let program = parse::parse(PATCH, &[]).unwrap();
+        let values = program.eval(vec![]).unwrap();
+        let sound = values.last().unwrap().as_sound().unwrap();
+        let mix = Sound::Mix(vec![
+            (0., sound.clone()),
+            (0.5, Arc::new(Sound::Noise(noise::Color::Pink, 3))),
+        ]);
+        let filtered = Sound::Filter(Filter::OnePole(1000.), Arc::new(mix));
+        let text = save(&filtered).unwrap();
+        let loaded = load(&text).unwrap();
+        assert_eq!(save(&loaded).unwrap(), text);
+        assert_eq!(
+            format!("{:?}", loaded.sample(100., 200).unwrap()),
+            format!("{:?}", filtered.sample(100., 200).unwrap())
+        );
+    }
+
+    #[test]
+    fn builtin_names() {
+        for func in [
+            Func::Osc,
+            Func::Lowpass,
+            Func::Concat,
+            Func::Noise(noise::Color::Pink),
+        ] {
+            let sound = Sound::Const(Box::new(Value::Func(func.clone())));
+            let text = save(&sound).unwrap();
+            assert_eq!(save(&load(&text).unwrap()).unwrap(), text);
+        }
+    }
+
+    #[test]
+    fn shared_nodes_stay_shared() {
+        let t = Arc::new(Sound::T);
+        let sound = Sound::App(Func::Mul, vec![t.clone(), t]);
+        let text = save(&sound).unwrap();
+        assert_eq!(text, "sound 1\n%0 = t\n%1 = app mul %0 %0\nout %1\n");
+        let Sound::App(_, args) = &*load(&text).unwrap() else {
+            panic!("not an application");
+        };
+        assert!(Arc::ptr_eq(&args[0], &args[1]));
+    }
+
+    #[test]
+    fn load_errors() {
+        let error = |input: &str| {
+            let err = load(input).unwrap_err();
+            (err.line, err.column)
+        };
+        assert_eq!(error("sound 1\n%0 = t\n%0 = t\nout %0\n"), (3, 1));
+        assert_eq!(error("sound 1\n%0 = t\nout %0\n%1 = t\n"), (4, 1));
+        assert_eq!(error("sound 1\n%0 = t\nout %0\nout %0\n"), (4, 1));
+        assert_eq!(error("sound 2\n"), (1, 7));
+        assert_eq!(error("sound 1\n%0 = t\n"), (3, 1));
+        assert_eq!(error("%0 = t\n"), (1, 1));
+        assert_eq!(error("sound 1\nout %9\n"), (2, 5));
+        assert_eq!(error("sound 1\n%0 = app nope %0\nout %0\n"), (2, 10));
+        // 空行とコメントは `out` の後にあってもよい
+        assert!(load("sound 1\n%0 = t\nout %0\n\n# end\n").is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use super::*;
+use crate::stream::{Stream, BLOCK};
+
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    /// [-1, 1] の範囲外の値はクリップする．
+    Int16,
+    Float32,
+}
+impl Format {
+    pub fn bits(self) -> u16 {
+        match self {
+            Format::Int16 => 16,
+            Format::Float32 => 32,
+        }
+    }
+    pub fn encode(self, writer: &mut impl Write, sample: f64) -> io::Result<()> {
+        match self {
+            Format::Int16 => {
+                let sample = (sample.clamp(-1., 1.) * i16::MAX as f64).round() as i16;
+                writer.write_all(&sample.to_le_bytes())
+            }
+            Format::Float32 => writer.write_all(&(sample as f32).to_le_bytes()),
+        }
+    }
+    pub fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            Format::Int16 => {
+                let sample = i16::from_le_bytes([bytes[0], bytes[1]]);
+                (sample as f64 / i16::MAX as f64).max(-1.)
+            }
+            Format::Float32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Sound(crate::Error),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{err}"),
+            Error::Sound(err) => write!(f, "{err}"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+impl From<crate::Error> for Error {
+    fn from(err: crate::Error) -> Error {
+        Error::Sound(err)
+    }
+}
+
+pub(crate) fn check_channels(channels: u16) -> io::Result<()> {
+    match channels {
+        0 => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            crate::Error::NoChannels.to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub trait AudioSink {
+    /// 書き出す前に一度だけ呼ばれる．`frames` は全体のフレーム数．
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()>;
+    fn write(&mut self, block: &[f64]) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+impl<S: AudioSink + ?Sized> AudioSink for &mut S {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        (**self).start(rate, channels, frames)
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        (**self).write(block)
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+impl<S: AudioSink + ?Sized> AudioSink for Box<S> {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        (**self).start(rate, channels, frames)
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        (**self).write(block)
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        (**self).finish()
+    }
+}
+
+pub fn render(
+    sink: &mut (impl AudioSink + ?Sized),
+    sound: &Sound,
+    rate: u32,
+    channels: u16,
+    duration: f64,
+) -> Result<(), Error> {
+    if channels == 0 {
+        return Err(crate::Error::NoChannels.into());
+    }
+    let n = (duration * rate as f64).round() as usize;
+    let mut stream = Stream::new(sound, rate as f64);
+    sink.start(rate, channels, n)?;
+    let mut block = vec![0.; BLOCK * channels as usize];
+    let mut rest = n;
+    while rest > 0 {
+        let block = &mut block[..rest.min(BLOCK) * channels as usize];
+        stream.fill(block, channels as usize)?;
+        sink.write(block)?;
+        rest -= block.len() / channels as usize;
+    }
+    sink.finish()?;
+    Ok(())
+}
+
+/// ヘッダなしの PCM をそのまま書き出す．標準出力に流して外部のプレイヤーに渡すのに使う．
+pub struct Raw<W> {
+    writer: W,
+    format: Format,
+}
+impl<W: Write> Raw<W> {
+    pub fn new(writer: W, format: Format) -> Raw<W> {
+        Raw { writer, format }
+    }
+}
+impl<W: Write> AudioSink for Raw<W> {
+    fn start(&mut self, _: u32, _: u16, _: usize) -> io::Result<()> {
+        Ok(())
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        for &sample in block {
+            self.format.encode(&mut self.writer, sample)?;
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Buffer {
+    pub rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f64>,
+}
+impl Buffer {
+    pub fn frame(&self, i: usize) -> Option<&[f64]> {
+        let channels = self.channels as usize;
+        self.samples.get(i * channels..(i + 1) * channels)
+    }
+    pub fn channel(&self, channel: usize) -> Vec<f64> {
+        self.samples
+            .iter()
+            .skip(channel)
+            .step_by(self.channels.max(1) as usize)
+            .copied()
+            .collect()
+    }
+}
+impl AudioSink for Buffer {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        self.rate = rate;
+        self.channels = channels;
+        self.samples.clear();
+        self.samples.reserve(frames * channels as usize);
+        Ok(())
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        self.samples.extend_from_slice(block);
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_to_buffer() {
+        let stereo = Sound::Merge(vec![
+            Arc::new(Sound::T),
+            Arc::new(Sound::Const(Box::new(Value::Float(0.5)))),
+        ]);
+        let mut buffer = Buffer::default();
+        render(&mut buffer, &stereo, 4, 2, 1.).unwrap();
+        assert_eq!((buffer.rate, buffer.channels), (4, 2));
+        assert_eq!(buffer.frame(1), Some(&[0.25, 0.5][..]));
+        assert_eq!(buffer.frame(4), None);
+        assert_eq!(buffer.channel(0), [0., 0.25, 0.5, 0.75]);
+        assert_eq!(buffer.channel(1), [0.5; 4]);
+
+        render(&mut buffer, &Sound::T, 4, 3, 0.5).unwrap();
+        assert_eq!(buffer.samples, [0., 0., 0., 0.25, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn raw_pcm() {
+        let mut raw = Raw::new(Vec::new(), Format::Int16);
+        render(
+            &mut raw,
+            &Sound::Const(Box::new(Value::Float(-2.))),
+            4,
+            1,
+            0.5,
+        )
+        .unwrap();
+        assert_eq!(raw.writer, [1, 128, 1, 128]);
+
+        let mut raw = Raw::new(Vec::new(), Format::Float32);
+        render(&mut raw, &Sound::T, 4, 1, 0.5).unwrap();
+        assert_eq!(raw.writer, [0., 0.25f32].map(f32::to_le_bytes).concat());
+    }
+
+    #[test]
+    fn zero_channels_is_an_error() {
+        let mut buffer = Buffer::default();
+        assert!(matches!(
+            render(&mut buffer, &Sound::T, 4, 0, 1.),
+            Err(Error::Sound(crate::Error::NoChannels))
+        ));
+        let mut stream = Stream::new(&Sound::T, 4.);
+        assert!(matches!(
+            stream.fill(&mut [0.; 4], 0),
+            Err(crate::Error::NoChannels)
+        ));
+    }
+}
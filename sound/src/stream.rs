@@ -0,0 +1,1027 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use super::*;
+
+pub const BLOCK: usize = 256;
+
+pub(crate) struct Stream {
+    node: Node,
+    rate: f64,
+    next: usize,
+    times: Vec<f64>,
+    onsets: Vec<usize>,
+    block: std::vec::IntoIter<Value>,
+}
+
+impl Stream {
+    pub fn new(sound: &Sound, rate: f64) -> Stream {
+        Stream {
+            node: Builder::build(sound, rate),
+            rate,
+            next: 0,
+            times: Vec::with_capacity(BLOCK),
+            onsets: Vec::new(),
+            block: Vec::new().into_iter(),
+        }
+    }
+    pub fn pos(&self) -> usize {
+        self.next - self.block.len()
+    }
+    /// `Value::Float` は全てのチャンネルに同じ値を入れる．
+    pub fn fill(&mut self, out: &mut [f64], channels: usize) -> Result<(), Error> {
+        if channels == 0 {
+            return Err(Error::NoChannels);
+        }
+        for frame in out.chunks_mut(channels) {
+            let pos = self.pos();
+            fill_frame(frame, pos, self.next().unwrap()?)?;
+        }
+        Ok(())
+    }
+    fn next_block(&mut self) -> Result<(), Error> {
+        self.times.clear();
+        self.times
+            .extend((self.next..self.next + BLOCK).map(|i| (i as f64) / self.rate));
+        self.next += BLOCK;
+        let mut values = Vec::with_capacity(BLOCK);
+        self.onsets.clear();
+        self.node.fill(&self.times, &mut values, &mut self.onsets)?;
+        self.block = values.into_iter();
+        Ok(())
+    }
+}
+impl Iterator for Stream {
+    type Item = Result<Value, Error>;
+    fn next(&mut self) -> Option<Result<Value, Error>> {
+        if self.block.len() == 0 {
+            if let Err(err) = self.next_block() {
+                return Some(Err(err));
+            }
+        }
+        self.block.next().map(Ok)
+    }
+}
+
+pub(crate) fn fill_frame(frame: &mut [f64], pos: usize, value: Value) -> Result<(), Error> {
+    match value {
+        Value::Float(x) => frame.fill(x),
+        Value::Frame(x) if x.len() == frame.len() => frame.copy_from_slice(&x),
+        Value::Frame(x) => return Err(Error::Channels(frame.len(), x.len())),
+        value => return Err(Error::NotFloat(pos, value)),
+    }
+    Ok(())
+}
+
+pub(crate) fn first_index(time: f64, rate: f64) -> usize {
+    let mut i = (time * rate).ceil().max(0.) as usize;
+    while i > 0 && ((i - 1) as f64) / rate >= time {
+        i -= 1;
+    }
+    while (i as f64) / rate < time {
+        i += 1;
+    }
+    i
+}
+
+/// `from` 番目から `to` 番目の手前までのサンプルの時刻から `offset` を引いて `sound` を評価する．
+/// ブロックの区切りは `Stream` と揃える．
+pub(crate) fn sample_range(
+    sound: &Sound,
+    rate: f64,
+    from: usize,
+    to: usize,
+    offset: f64,
+) -> Result<Vec<Value>, Error> {
+    let mut values = Vec::with_capacity(to.saturating_sub(from));
+    Cursor::new(sound, rate, from, offset).fill(to, &mut values)?;
+    Ok(values)
+}
+
+/// `sample_range` を区間を分けて呼んだときと同じ値を，状態を保ったまま少しずつ求める．
+pub(crate) struct Cursor {
+    node: Node,
+    rate: f64,
+    offset: f64,
+    pos: usize,
+    times: Vec<f64>,
+}
+
+impl Cursor {
+    pub fn new(sound: &Sound, rate: f64, from: usize, offset: f64) -> Cursor {
+        Cursor {
+            node: Builder::build(sound, rate),
+            rate,
+            offset,
+            pos: from,
+            times: Vec::with_capacity(BLOCK),
+        }
+    }
+    /// `to` 番目の手前まで進め，その間の値を `out` に追加する．
+    pub fn fill(&mut self, to: usize, out: &mut Vec<Value>) -> Result<(), Error> {
+        while self.pos < to {
+            let end = to.min((self.pos / BLOCK + 1) * BLOCK);
+            self.times.clear();
+            self.times
+                .extend((self.pos..end).map(|i| (i as f64) / self.rate - self.offset));
+            self.node.fill(&self.times, out, &mut Vec::new())?;
+            self.pos = end;
+        }
+        Ok(())
+    }
+}
+
+enum Node {
+    T,
+    Sample(Arc<sample::Sample>),
+    Noise(noise::State),
+    Curve(Arc<envelope::Curve>),
+    Adsr {
+        input: Input,
+        state: envelope::State,
+    },
+    Const(Value),
+    /// 音を返したときは引数と結果を `last` に覚えておき，
+    /// 同じ引数ならば同じ実体の音を返す．
+    App {
+        func: Func,
+        inputs: Vec<Input>,
+        last: Option<(Vec<Value>, Value)>,
+    },
+    Integrate {
+        input: Input,
+        sum: f64,
+        prev: Option<f64>,
+    },
+    Filter {
+        input: Input,
+        state: filter::State,
+    },
+    /// 時刻 t を (t - offset) / scale に変換して評価する．
+    Time {
+        input: Input,
+        times: Vec<f64>,
+        offset: f64,
+        scale: f64,
+    },
+    /// 区間の外は無音の値になる．
+    Segments(Value, Vec<Segment>),
+    Mix(Vec<Segment>),
+    Merge(Vec<Input>),
+    Channel(usize, Input),
+    /// 各時刻に得られた音を，その時刻で評価する．
+    /// 次の出来事までは同じ状態を使い続ける．
+    Flatten {
+        input: Input,
+        rate: f64,
+        current: Option<(Arc<Sound>, Box<Node>)>,
+        buf: Vec<Value>,
+    },
+    /// 出来事ごとに得られた音をその時刻から鳴らし始め，鳴っている音を全て足し合わせる．
+    Trigger {
+        input: Input,
+        rate: f64,
+        prev: Option<Arc<Sound>>,
+        voices: Vec<Voice>,
+    },
+}
+
+/// `Node::Trigger` で同時に鳴らす音の数の上限．超えたら最も古い音をそこで止める．
+const MAX_VOICES: usize = 64;
+
+struct Voice {
+    onset: f64,
+    end: Option<f64>,
+    node: Node,
+    times: Vec<f64>,
+    buf: Vec<Value>,
+}
+
+/// 直前と同じ時刻の列で評価されたら，前回の結果を返す．
+type Input = Rc<RefCell<Shared>>;
+struct Shared {
+    node: Node,
+    times: Vec<f64>,
+    values: Vec<Value>,
+    onsets: Vec<usize>,
+}
+impl Shared {
+    fn onset(&self, i: usize) -> bool {
+        self.onsets.binary_search(&i).is_ok()
+    }
+}
+fn eval(input: &Input, t: &[f64]) -> Result<(), Error> {
+    let mut shared = input.borrow_mut();
+    if shared.times != t {
+        let Shared {
+            node,
+            times,
+            values,
+            onsets,
+        } = &mut *shared;
+        times.clear();
+        values.clear();
+        onsets.clear();
+        node.fill(t, values, onsets)?;
+        onsets.sort_unstable();
+        onsets.dedup();
+        times.extend_from_slice(t);
+    }
+    Ok(())
+}
+
+/// 区間 [from, to) でのみ，時刻 t を t - offset に変換して評価する．
+struct Segment {
+    from: f64,
+    to: f64,
+    offset: f64,
+    input: Input,
+    times: Vec<f64>,
+    started: bool,
+}
+
+/// 同じ `Sound` でも，時刻の変換を挟んで参照されるものは別々の状態を持つ．
+struct Builder {
+    rate: f64,
+    inputs: HashMap<(*const Sound, usize), Input>,
+    contexts: usize,
+}
+impl Builder {
+    fn build(sound: &Sound, rate: f64) -> Node {
+        Builder {
+            rate,
+            inputs: HashMap::new(),
+            contexts: 0,
+        }
+        .node(sound, 0)
+    }
+    fn input(&mut self, sound: &Arc<Sound>, context: usize) -> Input {
+        let key = (Arc::as_ptr(sound), context);
+        if let Some(input) = self.inputs.get(&key) {
+            return input.clone();
+        }
+        let input = Rc::new(RefCell::new(Shared {
+            node: self.node(sound, context),
+            times: Vec::with_capacity(BLOCK),
+            values: Vec::with_capacity(BLOCK),
+            onsets: Vec::new(),
+        }));
+        self.inputs.insert(key, input.clone());
+        input
+    }
+    fn context(&mut self, sound: &Arc<Sound>) -> Input {
+        self.contexts += 1;
+        self.input(sound, self.contexts)
+    }
+    fn node(&mut self, sound: &Sound, context: usize) -> Node {
+        match sound {
+            Sound::T => Node::T,
+            Sound::Sample(sample) => Node::Sample(sample.clone()),
+            Sound::Curve(curve) => Node::Curve(curve.clone()),
+            Sound::Adsr(adsr, gate) => Node::Adsr {
+                input: self.input(gate, context),
+                state: adsr.state(),
+            },
+            Sound::Noise(color, seed) => Node::Noise(color.state(*seed)),
+            Sound::Const(value) => Node::Const(*value.clone()),
+            Sound::App(func, args) => Node::App {
+                func: func.clone(),
+                inputs: args
+                    .iter()
+                    .map(|sound| self.input(sound, context))
+                    .collect(),
+                last: None,
+            },
+            Sound::Integrate(sound) => Node::Integrate {
+                input: self.input(sound, context),
+                sum: 0.,
+                prev: None,
+            },
+            Sound::Filter(filter, sound) => Node::Filter {
+                input: self.input(sound, context),
+                state: filter.state(self.rate),
+            },
+            Sound::Shift(offset, sound) => self.time(sound, *offset, 1.),
+            Sound::Stretch(scale, sound) => self.time(sound, 0., *scale),
+            Sound::Trim(from, to, sound) => {
+                Node::Segments(silence(sound), vec![self.segment(*from, *to, 0., sound)])
+            }
+            Sound::Merge(sounds) => Node::Merge(
+                sounds
+                    .iter()
+                    .map(|sound| self.input(sound, context))
+                    .collect(),
+            ),
+            Sound::Channel(i, sound) => Node::Channel(*i, self.input(sound, context)),
+            Sound::Flatten(sound) => Node::Flatten {
+                input: self.input(sound, context),
+                rate: self.rate,
+                current: None,
+                buf: Vec::with_capacity(BLOCK),
+            },
+            Sound::Trigger(sound) => Node::Trigger {
+                input: self.input(sound, context),
+                rate: self.rate,
+                prev: None,
+                voices: Vec::new(),
+            },
+            Sound::Mix(sounds) => Node::Mix(
+                sounds
+                    .iter()
+                    .map(|(start, sound)| {
+                        let end = sound.end().map_or(f64::INFINITY, |end| start + end);
+                        self.segment(*start, end, *start, sound)
+                    })
+                    .collect(),
+            ),
+            Sound::Concat(sounds) => {
+                let mut start = 0.;
+                Node::Segments(
+                    silence(sound),
+                    sounds
+                        .iter()
+                        .map(|(len, sound)| {
+                            let segment = self.segment(start, start + len, start, sound);
+                            start += len;
+                            segment
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+    fn time(&mut self, sound: &Arc<Sound>, offset: f64, scale: f64) -> Node {
+        Node::Time {
+            input: self.context(sound),
+            times: Vec::with_capacity(BLOCK),
+            offset,
+            scale,
+        }
+    }
+    fn segment(&mut self, from: f64, to: f64, offset: f64, sound: &Arc<Sound>) -> Segment {
+        Segment {
+            from,
+            to,
+            offset,
+            input: self.context(sound),
+            times: Vec::with_capacity(BLOCK),
+            started: false,
+        }
+    }
+}
+
+impl Segment {
+    /// 区間の始まりと区間内の出来事を `onsets` に加える．
+    fn eval(&mut self, t: &[f64], onsets: &mut Vec<usize>) -> Result<Option<usize>, Error> {
+        let from = t.partition_point(|&t| t < self.from);
+        let to = t.partition_point(|&t| t < self.to);
+        if from >= to {
+            return Ok(None);
+        }
+        self.times.clear();
+        self.times
+            .extend(t[from..to].iter().map(|&t| t - self.offset));
+        eval(&self.input, &self.times)?;
+        if !self.started {
+            self.started = true;
+            onsets.push(from);
+        }
+        onsets.extend(self.input.borrow().onsets.iter().map(|i| from + i));
+        Ok(Some(from))
+    }
+}
+
+impl Node {
+    /// 時刻 `t` における値を `out` に追加する．`t` は昇順に並んでいる．
+    /// 新しい出来事 (区間の始まり) が起きた `t` の番号を `onsets` に加える．
+    fn fill(
+        &mut self,
+        t: &[f64],
+        out: &mut Vec<Value>,
+        onsets: &mut Vec<usize>,
+    ) -> Result<(), Error> {
+        match self {
+            Node::T => out.extend(t.iter().map(|&t| Value::Float(t))),
+            Node::Sample(sample) => out.extend(t.iter().map(|&t| sample.at(t))),
+            Node::Curve(curve) => out.extend(t.iter().map(|&t| Value::Float(curve.at(t)))),
+            Node::Adsr { input, state } => {
+                eval(input, t)?;
+                for (&t, value) in t.iter().zip(&input.borrow().values) {
+                    let gate = match *value {
+                        Value::Bool(gate) => gate,
+                        Value::Float(x) => x > 0.,
+                        _ => {
+                            return Err(Error::Input {
+                                node: "Adsr",
+                                expected: ty::Kind::Bool,
+                                found: value.clone(),
+                            })
+                        }
+                    };
+                    out.push(Value::Float(state.process(t, gate)));
+                }
+            }
+            Node::Noise(state) => out.extend(t.iter().map(|_| Value::Float(state.sample()))),
+            Node::Const(value) => out.extend(t.iter().map(|_| value.clone())),
+            Node::App { func, inputs, last } => {
+                for input in inputs.iter() {
+                    eval(input, t)?;
+                }
+                let inputs: Vec<_> = inputs.iter().map(|input| input.borrow()).collect();
+                for input in &inputs {
+                    onsets.extend_from_slice(&input.onsets);
+                }
+                for i in 0..t.len() {
+                    let args = || inputs.iter().map(|input| input.values[i].clone());
+                    if let Some((prev, value)) = last {
+                        if prev
+                            .iter()
+                            .zip(&inputs)
+                            .all(|(prev, input)| same(prev, &input.values[i]))
+                        {
+                            out.push(value.clone());
+                            continue;
+                        }
+                    }
+                    let value = func.call(args().collect())?;
+                    *last = match value {
+                        Value::Sound(_) => Some((args().collect(), value.clone())),
+                        _ => None,
+                    };
+                    out.push(value);
+                }
+            }
+            Node::Integrate { input, sum, prev } => {
+                eval(input, t)?;
+                for (&t, value) in t.iter().zip(&input.borrow().values) {
+                    let dt = prev.map_or(0., |prev| t - prev);
+                    *sum += float_input("Integrate", value)? * dt;
+                    *prev = Some(t);
+                    out.push(Value::Float(*sum));
+                }
+            }
+            Node::Filter { input, state } => {
+                eval(input, t)?;
+                for value in &input.borrow().values {
+                    out.push(Value::Float(state.process(float_input("Filter", value)?)));
+                }
+            }
+            Node::Time {
+                input,
+                times,
+                offset,
+                scale,
+            } => {
+                times.clear();
+                times.extend(t.iter().map(|&t| (t - *offset) / *scale));
+                eval(input, times)?;
+                let input = input.borrow();
+                out.extend_from_slice(&input.values);
+                onsets.extend_from_slice(&input.onsets);
+            }
+            Node::Segments(silence, segments) => {
+                let base = out.len();
+                out.extend(t.iter().map(|_| silence.clone()));
+                for segment in segments {
+                    if let Some(from) = segment.eval(t, onsets)? {
+                        let values = &segment.input.borrow().values;
+                        out[base + from..base + from + values.len()].clone_from_slice(values);
+                    }
+                }
+            }
+            Node::Mix(segments) => {
+                let base = out.len();
+                out.extend(t.iter().map(|_| Value::Float(0.)));
+                for segment in segments {
+                    if let Some(from) = segment.eval(t, onsets)? {
+                        for (slot, value) in out[base + from..]
+                            .iter_mut()
+                            .zip(&segment.input.borrow().values)
+                        {
+                            *slot = mix(slot, value)?;
+                        }
+                    }
+                }
+            }
+            Node::Merge(inputs) => {
+                for input in inputs.iter() {
+                    eval(input, t)?;
+                }
+                let inputs: Vec<_> = inputs.iter().map(|input| input.borrow()).collect();
+                for i in 0..t.len() {
+                    let frame = inputs
+                        .iter()
+                        .map(|input| float_input("Merge", &input.values[i]))
+                        .collect::<Result<_, _>>()?;
+                    out.push(Value::Frame(frame));
+                }
+            }
+            Node::Channel(i, input) => {
+                eval(input, t)?;
+                for value in &input.borrow().values {
+                    let x = match value {
+                        Value::Float(x) => *x,
+                        Value::Frame(frame) => {
+                            *frame.get(*i).ok_or(Error::Channel(*i, frame.len()))?
+                        }
+                        _ => {
+                            return Err(Error::Input {
+                                node: "Channel",
+                                expected: ty::Kind::Frame,
+                                found: value.clone(),
+                            })
+                        }
+                    };
+                    out.push(Value::Float(x));
+                }
+            }
+            Node::Flatten {
+                input,
+                rate,
+                current,
+                buf,
+            } => {
+                eval(input, t)?;
+                let input = input.borrow();
+                let mut i = 0;
+                while i < t.len() {
+                    let sound = nested_input("Flatten", &input.values[i])?;
+                    let node = match current {
+                        Some((prev, node)) if !input.onset(i) && Arc::ptr_eq(prev, sound) => node,
+                        _ => {
+                            &mut current
+                                .insert((sound.clone(), Box::new(Builder::build(sound, *rate))))
+                                .1
+                        }
+                    };
+                    let len = 1 + (i + 1..t.len())
+                        .take_while(|&j| {
+                            !input.onset(j)
+                                && matches!(&input.values[j], Value::Sound(next) if Arc::ptr_eq(next, sound))
+                        })
+                        .count();
+                    buf.clear();
+                    node.fill(&t[i..i + len], buf, &mut Vec::new())?;
+                    out.append(buf);
+                    i += len;
+                }
+            }
+            Node::Trigger {
+                input,
+                rate,
+                prev,
+                voices,
+            } => {
+                eval(input, t)?;
+                let input = input.borrow();
+                for (i, (&t, value)) in t.iter().zip(&input.values).enumerate() {
+                    let sound = nested_input("Trigger", value)?;
+                    if !input.onset(i) && prev.as_ref().is_some_and(|prev| Arc::ptr_eq(prev, sound)) {
+                        continue;
+                    }
+                    *prev = Some(sound.clone());
+                    let end = sound.end().map(|end| t + end);
+                    if end.is_some_and(|end| end <= t) {
+                        continue;
+                    }
+                    let mut active = voices
+                        .iter_mut()
+                        .filter(|voice| voice.end.is_none_or(|end| end > t));
+                    if let Some(oldest) = active.next() {
+                        if active.count() + 1 >= MAX_VOICES {
+                            oldest.end = Some(t);
+                        }
+                    }
+                    voices.push(Voice {
+                        onset: t,
+                        end,
+                        node: Builder::build(sound, *rate),
+                        times: Vec::with_capacity(BLOCK),
+                        buf: Vec::with_capacity(BLOCK),
+                    });
+                }
+                let base = out.len();
+                out.extend(t.iter().map(|_| Value::Float(0.)));
+                for voice in voices.iter_mut() {
+                    let from = t.partition_point(|&t| t < voice.onset);
+                    let to = match voice.end {
+                        Some(end) => t.partition_point(|&t| t < end),
+                        None => t.len(),
+                    };
+                    if from < to {
+                        voice.times.clear();
+                        voice
+                            .times
+                            .extend(t[from..to].iter().map(|&t| t - voice.onset));
+                        voice.buf.clear();
+                        voice
+                            .node
+                            .fill(&voice.times, &mut voice.buf, &mut Vec::new())?;
+                        for (slot, value) in out[base + from..].iter_mut().zip(&voice.buf) {
+                            *slot = mix(slot, value)?;
+                        }
+                    }
+                }
+                if let Some(&last) = t.last() {
+                    voices.retain(|voice| voice.end.is_none_or(|end| end > last));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `sound` の値の型の無音．型が定まらなければ `Value::Float(0.)` にしておき，使う側で型のエラーにする．
+fn silence(sound: &Sound) -> Value {
+    sound
+        .ty()
+        .ok()
+        .and_then(|ty| check::silence(&ty))
+        .unwrap_or(Value::Float(0.))
+}
+
+fn float_input(node: &'static str, value: &Value) -> Result<f64, Error> {
+    value.as_float().copied().ok_or_else(|| Error::Input {
+        node,
+        expected: ty::Kind::Float,
+        found: value.clone(),
+    })
+}
+
+fn nested_input<'a>(node: &'static str, value: &'a Value) -> Result<&'a Arc<Sound>, Error> {
+    match value {
+        Value::Sound(sound) => Ok(sound),
+        _ => Err(Error::Input {
+            node,
+            expected: ty::Kind::Sound,
+            found: value.clone(),
+        }),
+    }
+}
+
+/// 同じ値か．音は同じ実体かどうかで比べる．
+fn same(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Int(x), Value::Int(y)) => x == y,
+        (Value::Float(x), Value::Float(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Frame(x), Value::Frame(y)) => x == y,
+        (Value::Func(x), Value::Func(y)) => x == y,
+        (Value::Sound(x), Value::Sound(y)) => Arc::ptr_eq(x, y),
+        _ => false,
+    }
+}
+
+pub(crate) fn mix(left: &Value, right: &Value) -> Result<Value, Error> {
+    match (left, right) {
+        (Value::Float(x), Value::Float(y)) => Ok(Value::Float(x + y)),
+        (Value::Frame(x), Value::Frame(y)) if x.len() == y.len() => Ok(Value::Frame(
+            x.iter().zip(y.iter()).map(|(x, y)| x + y).collect(),
+        )),
+        (Value::Frame(x), Value::Frame(y)) => Err(Error::Channels(x.len(), y.len())),
+        (Value::Float(x), Value::Frame(y)) | (Value::Frame(y), Value::Float(x)) => {
+            Ok(Value::Frame(y.iter().map(|y| x + y).collect()))
+        }
+        (Value::Float(_) | Value::Frame(_), value) | (value, _) => Err(Error::Input {
+            node: "Mix",
+            expected: ty::Kind::Float,
+            found: value.clone(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_contiguous() {
+        let sound = Sound::App(Func::Add, vec![Arc::new(Sound::T), constant(1.)]);
+        let expected: Vec<_> = (0..3 * BLOCK + 10).map(|i| i as f64 / 100. + 1.).collect();
+        let values: Vec<_> = Stream::new(&sound, 100.)
+            .take(expected.len())
+            .map(|value| value.unwrap().into_float().unwrap())
+            .collect();
+        assert_eq!(values, expected);
+
+        // ブロックの途中で区切って埋めても同じ列になる
+        let mut stream = Stream::new(&sound, 100.);
+        let mut filled = vec![0.; expected.len()];
+        let (first, rest) = filled.split_at_mut(100);
+        stream.fill(first, 1).unwrap();
+        assert_eq!(stream.pos(), 100);
+        stream.fill(rest, 1).unwrap();
+        assert_eq!(filled, expected);
+    }
+
+    fn constant(x: f64) -> Arc<Sound> {
+        Arc::new(Sound::Const(Box::new(Value::Float(x))))
+    }
+
+    fn nested(sound: Arc<Sound>) -> Arc<Sound> {
+        Arc::new(Sound::Const(Box::new(Value::Sound(sound))))
+    }
+
+    fn floats(sound: &Sound, rate: f64, n: usize) -> Vec<f64> {
+        Stream::new(sound, rate)
+            .take(n)
+            .map(|value| value.unwrap().into_float().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn integrate_accumulates() {
+        let ramp = Sound::Integrate(constant(2.));
+        for (i, x) in floats(&ramp, 10., 3 * BLOCK).into_iter().enumerate() {
+            assert!((x - 2. * i as f64 / 10.).abs() < 1e-9, "#{i}: {x}");
+        }
+    }
+
+    #[test]
+    fn glide_keeps_phase_continuous() {
+        // 1 Hz から毎秒 2 Hz ずつ上がるので，位相は 2π(t + t^2)
+        let freq = Arc::new(Sound::App(
+            Func::Add,
+            vec![
+                constant(1.),
+                Arc::new(Sound::App(
+                    Func::Mul,
+                    vec![constant(2.), Arc::new(Sound::T)],
+                )),
+            ],
+        ));
+        let rate = 1000.;
+        let values = floats(&Sound::osc(Func::Sin, freq), rate, 1000);
+        for (i, x) in values.iter().enumerate() {
+            let t = i as f64 / rate;
+            assert!((x - (TAU * (t + t * t)).sin()).abs() < 0.02, "#{i}: {x}");
+        }
+        // 1 サンプルで進む位相は最大でも 2π × 3 Hz / rate
+        let step = TAU * 3. / rate;
+        assert!(values.windows(2).all(|w| (w[1] - w[0]).abs() <= step));
+    }
+
+    #[test]
+    fn time_transforms() {
+        let t = || Arc::new(Sound::T);
+        assert_eq!(
+            floats(&Sound::Shift(1., t()), 4., 4),
+            [-1., -0.75, -0.5, -0.25]
+        );
+        assert_eq!(
+            floats(&Sound::Stretch(2., t()), 4., 4),
+            [0., 0.125, 0.25, 0.375]
+        );
+        assert_eq!(
+            floats(&Sound::Trim(0.5, 1., t()), 4., 6),
+            [0., 0., 0.5, 0.75, 0., 0.]
+        );
+        let concat = Sound::Concat(vec![(0.5, t()), (0.5, Arc::new(Sound::Shift(1., t())))]);
+        assert_eq!(floats(&concat, 4., 6), [0., 0.25, -1., -0.75, 0., 0.]);
+    }
+
+    #[test]
+    fn segments_keep_state_across_blocks() {
+        let ramp = Arc::new(Sound::Integrate(constant(1.)));
+        let concat = Sound::Concat(vec![(3., ramp), (3., Arc::new(Sound::T))]);
+        for (i, x) in floats(&concat, 100., 600).into_iter().enumerate() {
+            let expected = (i % 300) as f64 / 100.;
+            assert!((x - expected).abs() < 1e-9, "#{i}: {x}");
+        }
+    }
+
+    #[test]
+    fn shared_nodes_run_once_per_block() {
+        // 2 回評価されると遅延線に同じブロックが 2 度入り，ずれてしまう
+        let delayed = Arc::new(Sound::Filter(
+            filter::Filter::Delay(0.01),
+            Arc::new(Sound::T),
+        ));
+        let twice = Sound::App(Func::Add, vec![delayed.clone(), delayed]);
+        for (i, x) in floats(&twice, 100., 3 * BLOCK).into_iter().enumerate() {
+            let expected = 2. * i.saturating_sub(1) as f64 / 100.;
+            assert!((x - expected).abs() < 1e-9, "#{i}: {x}");
+        }
+
+        // 区間ごとに時刻が変わるので，同じ音でも別々の状態を持つ
+        let ramp = Arc::new(Sound::Integrate(constant(1.)));
+        let concat = Sound::Concat(vec![(1., ramp.clone()), (1., ramp)]);
+        assert_eq!(
+            floats(&concat, 4., 8),
+            [0., 0.25, 0.5, 0.75, 0., 0.25, 0.5, 0.75]
+        );
+    }
+
+    #[test]
+    fn shared_lfo_in_mix() {
+        // 声部の中では 1 度だけ評価し，声部ごとには別の状態を持つ
+        let lfo = Arc::new(Sound::Integrate(constant(1.)));
+        let voice = Arc::new(Sound::App(Func::Mul, vec![lfo.clone(), lfo]));
+        let mix = Sound::Mix(vec![(0., voice.clone()), (1., voice)]);
+        for (i, x) in floats(&mix, 100., 3 * BLOCK).into_iter().enumerate() {
+            let t = i as f64 / 100.;
+            let expected = t * t + if t >= 1. { (t - 1.) * (t - 1.) } else { 0. };
+            assert!((x - expected).abs() < 1e-9, "#{i}: {x}");
+        }
+    }
+
+    #[test]
+    fn shared_noise_is_drawn_once() {
+        // 別々に評価されると乱数列が進み，差が 0 にならない
+        let noise = Arc::new(Sound::Noise(noise::Color::White, 1));
+        let diff = Sound::App(Func::Sub, vec![noise.clone(), noise.clone()]);
+        assert!(floats(&diff, 100., 3 * BLOCK).iter().all(|&x| x == 0.));
+        let noise = floats(&noise, 100., 3 * BLOCK);
+        assert!(noise.iter().any(|&x| x != 0.));
+    }
+
+    #[test]
+    fn merge_and_split_channels() {
+        let stereo = Arc::new(Sound::Merge(vec![Arc::new(Sound::T), constant(1.)]));
+        let mut stream = Stream::new(&stereo, 4.);
+        let mut out = [0.; 6];
+        stream.fill(&mut out, 2).unwrap();
+        assert_eq!(out, [0., 1., 0.25, 1., 0.5, 1.]);
+        assert!(matches!(
+            stream.fill(&mut out, 3),
+            Err(Error::Channels(3, 2))
+        ));
+
+        let swapped = Sound::Merge(vec![
+            Arc::new(Sound::Channel(1, stereo.clone())),
+            Arc::new(Sound::Channel(0, stereo.clone())),
+        ]);
+        let mut out = [0.; 4];
+        Stream::new(&swapped, 4.).fill(&mut out, 2).unwrap();
+        assert_eq!(out, [1., 0., 1., 0.25]);
+
+        let missing = Sound::Channel(2, stereo);
+        assert!(matches!(
+            Stream::new(&missing, 4.).next().unwrap(),
+            Err(Error::Channel(2, 2))
+        ));
+
+        // モノラルの音はすべてのチャンネルに同じ値を書く
+        let mut out = [0.; 4];
+        Stream::new(&Sound::T, 4.).fill(&mut out, 2).unwrap();
+        assert_eq!(out, [0., 0., 0.25, 0.25]);
+    }
+
+    #[test]
+    fn flatten_and_trigger() {
+        let doubled = Sound::App(Func::Mul, vec![constant(2.), Arc::new(Sound::T)]);
+        let switch = Arc::new(Sound::Concat(vec![
+            (0.5, nested(Arc::new(Sound::T))),
+            (0.5, nested(Arc::new(doubled))),
+        ]));
+        // `Flatten` は外側の時刻で評価し，区間の外は無音
+        let flat = Sound::Flatten(switch.clone());
+        assert_eq!(floats(&flat, 4., 8), [0., 0.25, 1., 1.5, 0., 0., 0., 0.]);
+        // `Trigger` は現れた時刻を 0 として鳴らし始め，前の音と重ねる
+        let hits = Sound::Trigger(switch);
+        assert_eq!(
+            floats(&hits, 4., 8),
+            [0., 0.25, 0.5, 1.25, 2., 2.75, 3.5, 4.25]
+        );
+
+        let flat = Sound::Flatten(Arc::new(Sound::T));
+        assert!(matches!(
+            Stream::new(&flat, 4.).next().unwrap(),
+            Err(Error::Input {
+                node: "Flatten",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn fill_reports_position() {
+        let sound = Sound::Const(Box::new(Value::Func(Func::Sin)));
+        let mut stream = Stream::new(&sound, 100.);
+        assert!(matches!(
+            stream.fill(&mut [0.; 4], 1),
+            Err(Error::NotFloat(0, Value::Func(_)))
+        ));
+        assert!(matches!(
+            stream.fill(&mut [0.; 4], 1),
+            Err(Error::NotFloat(1, Value::Func(_)))
+        ));
+    }
+
+    #[test]
+    fn reversed_window_is_silent() {
+        let sound = Sound::Trim(0.5, 0.2, Arc::new(Sound::T));
+        assert_eq!(floats(&sound, 10., 10), vec![0.; 10]);
+    }
+
+    #[test]
+    fn reversed_window_is_rejected() {
+        let trim = Func::Trim.call(vec![
+            Value::Float(0.5),
+            Value::Float(0.2),
+            Value::Sound(Arc::new(Sound::T)),
+        ]);
+        assert!(matches!(trim, Err(Error::Window(from, to)) if from == 0.5 && to == 0.2));
+        let concat = Sound::concat(vec![(1., Arc::new(Sound::T)), (-0.5, Arc::new(Sound::T))]);
+        assert!(matches!(concat, Err(Error::Window(from, to)) if from == 1. && to == 0.5));
+        let concat = Func::Concat.call(vec![Value::Float(-1.), Value::Sound(Arc::new(Sound::T))]);
+        assert!(matches!(concat, Err(Error::Window(from, to)) if from == 0. && to == -1.));
+        let loaded = serial::load("sound 1\n%0 = t\n%1 = concat 1 %0 -0.5 %0 1 %0\nout %1\n");
+        assert_eq!(loaded.unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn bad_scale_is_rejected() {
+        // 伸縮率が 0 だと時刻が変わらず，前のブロックの値がそのまま使われてしまう
+        let noise = Arc::new(Sound::Noise(noise::Color::White, 1));
+        for scale in [0., -1., f64::INFINITY, f64::NAN] {
+            let stretch =
+                Func::Stretch.call(vec![Value::Float(scale), Value::Sound(noise.clone())]);
+            assert!(matches!(stretch, Err(Error::Scale(_))));
+        }
+        let loaded = serial::load("sound 1\n%0 = noise white 1\n%1 = stretch 0 %0\nout %1\n");
+        assert_eq!(loaded.unwrap_err().line, 3);
+    }
+
+    #[test]
+    fn gaps_are_typed_silence() {
+        let gate = Arc::new(Sound::Trim(
+            0.,
+            1.,
+            Arc::new(Sound::App(
+                Func::Less,
+                vec![
+                    Arc::new(Sound::T),
+                    Arc::new(Sound::Const(Box::new(Value::Float(0.5)))),
+                ],
+            )),
+        ));
+        let sound = Sound::App(
+            Func::If,
+            vec![
+                gate,
+                Arc::new(Sound::Const(Box::new(Value::Float(1.)))),
+                Arc::new(Sound::Const(Box::new(Value::Float(0.)))),
+            ],
+        );
+        assert_eq!(floats(&sound, 4., 8), [1., 1., 0., 0., 0., 0., 0., 0.]);
+
+        let stereo = Arc::new(Sound::Trim(
+            0.,
+            0.5,
+            Arc::new(Sound::Const(Box::new(Value::Frame(Arc::new([1., 2.]))))),
+        ));
+        let right = Sound::Channel(1, stereo.clone());
+        assert_eq!(floats(&right, 4., 4), [2., 2., 0., 0.]);
+        let mono = Sound::App(Func::MixDown, vec![stereo]);
+        assert_eq!(floats(&mono, 4., 4), [1.5, 1.5, 0., 0.]);
+
+        let nested = Sound::Flatten(Arc::new(Sound::Trim(
+            0.,
+            0.5,
+            Arc::new(Sound::Const(Box::new(Value::Sound(Arc::new(Sound::T))))),
+        )));
+        assert_eq!(floats(&nested, 4., 4), [0., 0.25, 0., 0.]);
+    }
+
+    #[test]
+    fn repeated_hits_retrigger() {
+        let hit = nested(Arc::new(Sound::Trim(0., 0.25, constant(1.))));
+        let hits = Sound::Trigger(Arc::new(Sound::Concat(vec![
+            (0.5, hit.clone()),
+            (0.5, hit),
+        ])));
+        assert_eq!(
+            floats(&hits, 8., 10),
+            [1., 1., 0., 0., 1., 1., 0., 0., 0., 0.]
+        );
+    }
+
+    #[test]
+    fn flatten_keeps_state_of_applied_sound() {
+        let smooth = Arc::new(Sound::Filter(filter::Filter::OnePole(1000.), constant(1.)));
+        let shifted = Arc::new(Sound::App(
+            Func::Shift,
+            vec![constant(0.), nested(smooth.clone())],
+        ));
+        let expected = floats(&smooth, 8000., 16);
+        assert_eq!(floats(&Sound::Flatten(shifted), 8000., 16), expected);
+        assert!(expected.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn trigger_caps_voices() {
+        let one = nested(constant(1.));
+        let once = Sound::Trigger(Arc::new(Sound::App(
+            Func::Shift,
+            vec![constant(0.), one.clone()],
+        )));
+        assert_eq!(floats(&once, 100., 300), vec![1.; 300]);
+        let every = Sound::Trigger(Arc::new(Sound::App(
+            Func::Shift,
+            vec![Arc::new(Sound::T), one],
+        )));
+        let values = floats(&every, 100., 300);
+        assert_eq!(values[9], 10.);
+        assert_eq!(values[299], MAX_VOICES as f64);
+    }
+}
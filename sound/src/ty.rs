@@ -1,13 +1,16 @@
-#[derive(Clone, Copy, Debug)]
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Kind {
     Int,
     Float,
     Bool,
+    Frame,
     Sound,
     Func,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Ty {
     kind: Kind,
     args: Vec<Ty>,
@@ -31,6 +34,53 @@ impl Ty {
             args: vec![],
         }
     }
+    /// チャンネル数だけ `Float` を型引数に持つ．
+    pub fn frame(channels: usize) -> Ty {
+        Ty {
+            kind: Kind::Frame,
+            args: vec![Ty::float(); channels],
+        }
+    }
+    pub fn sound(ty: Ty) -> Ty {
+        Ty {
+            kind: Kind::Sound,
+            args: vec![ty],
+        }
+    }
+    pub fn func(ret: Ty, params: Vec<Ty>) -> Ty {
+        Ty {
+            kind: Kind::Func,
+            args: std::iter::once(ret).chain(params).collect(),
+        }
+    }
+    pub fn to_expr(&self) -> Expr {
+        Expr::App(
+            self.kind,
+            self.args.iter().map(|ty| Arg::Expr(ty.to_expr())).collect(),
+        )
+    }
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+    pub fn as_sound(&self) -> Option<&Ty> {
+        match self.kind {
+            Kind::Sound => self.args.first(),
+            _ => None,
+        }
+    }
+    /// フレームのチャンネル数．
+    pub fn as_frame(&self) -> Option<usize> {
+        match self.kind {
+            Kind::Frame => Some(self.args.len()),
+            _ => None,
+        }
+    }
+    pub fn as_func(&self) -> Option<(&Ty, &[Ty])> {
+        match self.kind {
+            Kind::Func => self.args.split_first(),
+            _ => None,
+        }
+    }
 }
 
 pub struct Func {
@@ -49,62 +99,294 @@ pub enum Arg {
     Expand(Expr),
 }
 
+#[derive(Debug)]
+pub enum Mismatch {
+    Arg(usize, Box<Mismatch>),
+    /// 引数の個数が合わない．`variadic` なら `expected` は固定部分の個数．
+    Count {
+        expected: usize,
+        variadic: bool,
+        found: usize,
+    },
+    Kind { expected: Kind, found: Ty },
+    Conflict { var: usize, first: Ty, second: Ty },
+    Len(usize, usize),
+    Unbound(usize),
+    /// `Arg::Expand` の外に範囲があるか，展開が入れ子になっている．
+    Expand,
+}
+
 impl Func {
-    pub fn eval(&self, tys: &[Ty]) -> Ty {
-        let args = std::iter::once(self.ret.eval(tys))
-            .chain(expand_args(&self.args, tys).into_iter())
-            .collect::<Result<_, _>>()
-            .unwrap();
-        Ty {
+    pub fn eval(&self, args: &[Ty]) -> Result<Ty, Mismatch> {
+        let mut subst = Subst::default();
+        subst.match_args(&self.args, args, None, true)?;
+        let tys = subst.into_tys()?;
+        self.ret.eval(&tys)?.one()
+    }
+    pub fn instantiate(&self, tys: &[Ty]) -> Result<Ty, Mismatch> {
+        let args = std::iter::once(self.ret.eval(tys)?.one())
+            .chain(expand_args(&self.args, tys)?.into_iter().map(Inst::one))
+            .collect::<Result<_, _>>()?;
+        Ok(Ty {
             kind: Kind::Func,
             args,
+        })
+    }
+    pub fn mono(&self) -> Option<Ty> {
+        if self.ret.has_var() || self.args.iter().any(Arg::has_var) {
+            return None;
         }
+        self.instantiate(&[]).ok()
     }
 }
-fn expand_args(args: &[Arg], tys: &[Ty]) -> Vec<Result<Ty, Vec<Ty>>> {
+impl Expr {
+    fn has_var(&self) -> bool {
+        match self {
+            Expr::Var(_) | Expr::Range(..) => true,
+            Expr::App(_, args) => args.iter().any(Arg::has_var),
+        }
+    }
+}
+impl Arg {
+    fn has_var(&self) -> bool {
+        match self {
+            Arg::Expr(expr) | Arg::Expand(expr) => expr.has_var(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Subst {
+    vars: HashMap<usize, Ty>,
+    /// `Expr::Range(from, to)` ごとに，展開された各要素の型．
+    ranges: HashMap<(usize, usize), Vec<Option<Ty>>>,
+}
+impl Subst {
+    /// `index` は `Arg::Expand` の中で何番目の要素を照合しているか．
+    /// `top` なら，失敗した引数の位置を `Mismatch::Arg` で記録する．
+    fn match_args(
+        &mut self,
+        patterns: &[Arg],
+        tys: &[Ty],
+        index: Option<usize>,
+        top: bool,
+    ) -> Result<(), Mismatch> {
+        let expands = patterns
+            .iter()
+            .filter(|arg| matches!(arg, Arg::Expand(_)))
+            .count();
+        let fixed = patterns.len() - expands;
+        let count = || Mismatch::Count {
+            expected: fixed,
+            variadic: expands > 0,
+            found: tys.len(),
+        };
+        let len = match expands {
+            0 if tys.len() == fixed => 0,
+            0 => return Err(count()),
+            _ => match tys.len().checked_sub(fixed) {
+                Some(rest) if rest % expands == 0 => rest / expands,
+                _ => return Err(count()),
+            },
+        };
+        let mut tys = tys.iter().enumerate();
+        for pattern in patterns {
+            let mut next = |subst: &mut Subst, expr, index| {
+                let (pos, ty) = tys.next().unwrap();
+                subst.match_expr(expr, ty, index).map_err(|err| match top {
+                    true => Mismatch::Arg(pos, Box::new(err)),
+                    false => err,
+                })
+            };
+            match pattern {
+                Arg::Expr(expr) => next(self, expr, index)?,
+                Arg::Expand(expr) => {
+                    if index.is_some() {
+                        return Err(Mismatch::Expand);
+                    }
+                    self.set_len(expr, len)?;
+                    for i in 0..len {
+                        next(self, expr, Some(i))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    fn match_expr(
+        &mut self,
+        pattern: &Expr,
+        ty: &Ty,
+        index: Option<usize>,
+    ) -> Result<(), Mismatch> {
+        let (var, slot) = match *pattern {
+            Expr::Var(i) => (i, self.vars.get(&i).cloned()),
+            Expr::Range(from, to) => {
+                let i = index.ok_or(Mismatch::Expand)?;
+                let range = self.ranges.get(&(from, to)).ok_or(Mismatch::Expand)?;
+                (from + i, range[i].clone())
+            }
+            Expr::App(kind, ref args) => {
+                if kind != ty.kind {
+                    return Err(Mismatch::Kind {
+                        expected: kind,
+                        found: ty.clone(),
+                    });
+                }
+                return self.match_args(args, &ty.args, index, false);
+            }
+        };
+        match slot {
+            Some(first) if first != *ty => Err(Mismatch::Conflict {
+                var,
+                first,
+                second: ty.clone(),
+            }),
+            Some(_) => Ok(()),
+            None => {
+                match *pattern {
+                    Expr::Range(from, to) => {
+                        self.ranges.get_mut(&(from, to)).unwrap()[index.unwrap()] = Some(ty.clone())
+                    }
+                    _ => {
+                        self.vars.insert(var, ty.clone());
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+    fn set_len(&mut self, expr: &Expr, len: usize) -> Result<(), Mismatch> {
+        match *expr {
+            Expr::Var(_) => Ok(()),
+            Expr::Range(from, to) => {
+                let range = self
+                    .ranges
+                    .entry((from, to))
+                    .or_insert_with(|| vec![None; len]);
+                match range.len() {
+                    n if n == len => Ok(()),
+                    n => Err(Mismatch::Len(n, len)),
+                }
+            }
+            Expr::App(_, ref args) => args.iter().try_for_each(|arg| match arg {
+                Arg::Expr(expr) => self.set_len(expr, len),
+                Arg::Expand(_) => Ok(()),
+            }),
+        }
+    }
+    fn into_tys(self) -> Result<Vec<Ty>, Mismatch> {
+        let mut n = None;
+        for (&(from, to), tys) in &self.ranges {
+            let len = from + tys.len() + to;
+            match n {
+                Some(n) if n != len => return Err(Mismatch::Len(n, len)),
+                _ => n = Some(len),
+            }
+        }
+        let n = n.unwrap_or_else(|| self.vars.keys().max().map_or(0, |&i| i + 1));
+        let mut tys = vec![None; n];
+        for (i, ty) in self.vars {
+            *tys.get_mut(i).ok_or(Mismatch::Len(n, i + 1))? = Some(ty);
+        }
+        for ((from, _), range) in self.ranges {
+            for (i, (slot, ty)) in tys[from..].iter_mut().zip(range).enumerate() {
+                let ty = ty.ok_or(Mismatch::Unbound(from + i))?;
+                match slot.take() {
+                    Some(first) if first != ty => {
+                        return Err(Mismatch::Conflict {
+                            var: from + i,
+                            first,
+                            second: ty,
+                        })
+                    }
+                    _ => *slot = Some(ty),
+                }
+            }
+        }
+        tys.into_iter()
+            .enumerate()
+            .map(|(i, ty)| ty.ok_or(Mismatch::Unbound(i)))
+            .collect()
+    }
+}
+
+/// 型変数を代入した結果．範囲を含む式は型の列になる．
+enum Inst {
+    One(Ty),
+    Many(Vec<Ty>),
+}
+impl Inst {
+    fn one(self) -> Result<Ty, Mismatch> {
+        match self {
+            Inst::One(ty) => Ok(ty),
+            Inst::Many(_) => Err(Mismatch::Expand),
+        }
+    }
+}
+
+fn expand_args(args: &[Arg], tys: &[Ty]) -> Result<Vec<Inst>, Mismatch> {
     let mut ret = Vec::new();
     for arg in args {
         match arg {
-            Arg::Expr(expr) => ret.push(expr.eval(tys)),
-            Arg::Expand(expr) => ret.extend(expr.eval(tys).unwrap_err().into_iter().map(Ok)),
+            Arg::Expr(expr) => ret.push(expr.eval(tys)?),
+            Arg::Expand(expr) => match expr.eval(tys)? {
+                Inst::Many(tys) => ret.extend(tys.into_iter().map(Inst::One)),
+                Inst::One(_) => return Err(Mismatch::Expand),
+            },
         }
     }
-    ret
+    Ok(ret)
 }
 impl Expr {
-    fn eval(&self, tys: &[Ty]) -> Result<Ty, Vec<Ty>> {
+    fn eval(&self, tys: &[Ty]) -> Result<Inst, Mismatch> {
         match *self {
-            Expr::Var(i) => Ok(tys[i].clone()),
-            Expr::Range(from, to) => {
-                let to = tys.len() - to;
-                Err(tys[from..to].to_vec())
-            }
+            Expr::Var(i) => tys
+                .get(i)
+                .cloned()
+                .map(Inst::One)
+                .ok_or(Mismatch::Unbound(i)),
+            Expr::Range(from, to) => match tys.len().checked_sub(to) {
+                Some(to) if from <= to => Ok(Inst::Many(tys[from..to].to_vec())),
+                _ => Err(Mismatch::Unbound(from)),
+            },
             Expr::App(kind, ref args) => {
-                let expanded = expand_args(args, tys);
-                let mut lens = expanded
-                    .iter()
-                    .filter_map(|e| e.as_ref().map_err(|tys| tys.len()).err());
+                let expanded = expand_args(args, tys)?;
+                let mut lens = expanded.iter().filter_map(|inst| match inst {
+                    Inst::One(_) => None,
+                    Inst::Many(tys) => Some(tys.len()),
+                });
                 match lens.next() {
                     Some(len) => {
-                        assert!(lens.all(|n| n == len));
+                        if let Some(n) = lens.find(|&n| n != len) {
+                            return Err(Mismatch::Len(len, n));
+                        }
                         let mut iters: Vec<_> = expanded
                             .into_iter()
-                            .map(|e| match e {
-                                Ok(ty) => vec![ty; len].into_iter(),
-                                Err(tys) => tys.into_iter(),
+                            .map(|inst| match inst {
+                                Inst::One(ty) => vec![ty; len].into_iter(),
+                                Inst::Many(tys) => tys.into_iter(),
                             })
                             .collect();
-                        Err((0..len)
-                            .map(|_| Ty {
-                                kind,
-                                args: iters.iter_mut().map(|iter| iter.next().unwrap()).collect(),
-                            })
-                            .collect())
+                        Ok(Inst::Many(
+                            (0..len)
+                                .map(|_| Ty {
+                                    kind,
+                                    args: iters
+                                        .iter_mut()
+                                        .map(|iter| iter.next().unwrap())
+                                        .collect(),
+                                })
+                                .collect(),
+                        ))
                     }
-                    None => Ok(Ty {
+                    None => Ok(Inst::One(Ty {
                         kind,
-                        args: expanded.into_iter().collect::<Result<_, _>>().unwrap(),
-                    }),
+                        args: expanded
+                            .into_iter()
+                            .map(Inst::one)
+                            .collect::<Result<_, _>>()?,
+                    })),
                 }
             }
         }
@@ -204,3 +486,117 @@ impl fmt::Debug for Arg {
         }
     }
 }
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Arg(pos, err) => write!(f, "argument #{pos}: {err}"),
+            Mismatch::Count {
+                expected,
+                variadic: false,
+                found,
+            } => write!(f, "expected {expected} argument(s), found {found}"),
+            Mismatch::Count {
+                expected,
+                variadic: true,
+                found,
+            } => write!(
+                f,
+                "expected {expected} argument(s) followed by expansions, found {found}"
+            ),
+            Mismatch::Kind { expected, found } => {
+                write!(f, "expected {expected:?}, found {found:?}")
+            }
+            Mismatch::Conflict { var, first, second } => {
+                write!(f, "#{var} cannot be both {first:?} and {second:?}")
+            }
+            Mismatch::Len(first, second) => {
+                write!(f, "ranges of length {first} and {second} do not match")
+            }
+            Mismatch::Unbound(var) => write!(f, "#{var} is not determined"),
+            Mismatch::Expand => write!(f, "ranges must be expanded exactly once"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `App[#0, #1:#-0]`．関数と，その引数の数だけの音を受け取る．
+    fn app() -> Func {
+        Func {
+            args: vec![
+                Arg::Expr(Expr::App(
+                    Kind::Func,
+                    vec![Arg::Expr(Expr::Var(0)), Arg::Expand(Expr::Range(1, 0))],
+                )),
+                Arg::Expand(Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Range(1, 0))])),
+            ],
+            ret: Expr::App(Kind::Sound, vec![Arg::Expr(Expr::Var(0))]),
+        }
+    }
+
+    #[test]
+    fn expand_args() {
+        let app = app();
+        assert_eq!(app.mono(), None);
+        let inst = app
+            .instantiate(&[Ty::int(), Ty::float(), Ty::bool()])
+            .unwrap();
+        let (ret, args) = inst.as_func().unwrap();
+        assert_eq!(ret, &Ty::sound(Ty::int()));
+        assert_eq!(args[1..], [Ty::sound(Ty::float()), Ty::sound(Ty::bool())]);
+        let (inner, params) = args[0].as_func().unwrap();
+        assert_eq!(
+            (inner, params),
+            (&Ty::int(), &[Ty::float(), Ty::bool()][..])
+        );
+        assert_eq!(app.eval(args).unwrap(), Ty::sound(Ty::int()));
+    }
+
+    #[test]
+    fn mismatches() {
+        let app = app();
+        let args = app
+            .instantiate(&[Ty::int(), Ty::float(), Ty::bool()])
+            .unwrap()
+            .as_func()
+            .unwrap()
+            .1
+            .to_vec();
+        assert!(matches!(
+            app.eval(&[args[0].clone(), args[2].clone()]),
+            Err(Mismatch::Len(2, 1))
+        ));
+        match app.eval(&[args[0].clone(), args[2].clone(), args[1].clone()]) {
+            Err(Mismatch::Arg(1, reason)) => {
+                assert!(matches!(*reason, Mismatch::Conflict { var: 1, .. }))
+            }
+            other => panic!("{other:?}"),
+        }
+        assert!(matches!(
+            app.eval(&[args[1].clone(), args[2].clone()]),
+            Err(Mismatch::Arg(0, _))
+        ));
+        assert!(matches!(
+            app.eval(&[]),
+            Err(Mismatch::Count {
+                expected: 1,
+                variadic: true,
+                found: 0
+            })
+        ));
+
+        let same = Func {
+            args: vec![Arg::Expr(Expr::Var(0)), Arg::Expr(Expr::Var(0))],
+            ret: Expr::Var(0),
+        };
+        assert_eq!(same.eval(&[Ty::int(), Ty::int()]).unwrap(), Ty::int());
+        match same.eval(&[Ty::int(), Ty::float()]) {
+            Err(Mismatch::Arg(1, reason)) => {
+                assert!(matches!(*reason, Mismatch::Conflict { var: 0, .. }))
+            }
+            other => panic!("{other:?}"),
+        }
+    }
+}
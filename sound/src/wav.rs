@@ -0,0 +1,331 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use super::*;
+use crate::{
+    sample::Sample,
+    sink::{AudioSink, Error, Format},
+};
+
+fn tag(format: Format) -> u16 {
+    match format {
+        Format::Int16 => 1,
+        Format::Float32 => 3,
+    }
+}
+
+pub struct Sink<W> {
+    writer: W,
+    format: Format,
+}
+impl<W: Write> Sink<W> {
+    pub fn new(writer: W, format: Format) -> Sink<W> {
+        Sink { writer, format }
+    }
+}
+impl<W: Write> AudioSink for Sink<W> {
+    fn start(&mut self, rate: u32, channels: u16, frames: usize) -> io::Result<()> {
+        let format = self.format;
+        let writer = &mut self.writer;
+        let (fmt_len, fact_len) = match format {
+            Format::Int16 => (16, 0),
+            Format::Float32 => (18, 12),
+        };
+        let too_long = || io::Error::new(io::ErrorKind::InvalidInput, "too long for a WAV file");
+        let block_align = channels
+            .checked_mul(format.bits())
+            .map(|bits| bits / 8)
+            .ok_or_else(too_long)?;
+        let data_len = frames
+            .checked_mul(block_align as usize)
+            .and_then(|len| u32::try_from(len).ok())
+            .ok_or_else(too_long)?;
+        let riff_len = (4 + (8 + fmt_len) + fact_len + 8u32)
+            .checked_add(data_len)
+            .ok_or_else(too_long)?;
+        let byte_rate = rate
+            .checked_mul(block_align as u32)
+            .ok_or_else(too_long)?;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&riff_len.to_le_bytes())?;
+        writer.write_all(b"WAVE")?;
+
+        writer.write_all(b"fmt ")?;
+        writer.write_all(&fmt_len.to_le_bytes())?;
+        writer.write_all(&tag(format).to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&format.bits().to_le_bytes())?;
+        if let Format::Float32 = format {
+            writer.write_all(&0u16.to_le_bytes())?;
+            writer.write_all(b"fact")?;
+            writer.write_all(&4u32.to_le_bytes())?;
+            writer.write_all(&(frames as u32).to_le_bytes())?;
+        }
+
+        writer.write_all(b"data")?;
+        writer.write_all(&data_len.to_le_bytes())
+    }
+    fn write(&mut self, block: &[f64]) -> io::Result<()> {
+        for &sample in block {
+            self.format.encode(&mut self.writer, sample)?;
+        }
+        Ok(())
+    }
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub fn write(
+    writer: impl Write,
+    sound: &Sound,
+    rate: u32,
+    channels: u16,
+    duration: f64,
+    format: Format,
+) -> Result<(), Error> {
+    sink::render(
+        &mut Sink::new(writer, format),
+        sound,
+        rate,
+        channels,
+        duration,
+    )
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    NotWav,
+    Missing(&'static str),
+    Unsupported {
+        tag: u16,
+        bits: u16,
+    },
+    Format(&'static str),
+}
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(err) => write!(f, "{err}"),
+            ReadError::NotWav => write!(f, "not a WAV file"),
+            ReadError::Missing(chunk) => write!(f, "missing or truncated `{chunk}` chunk"),
+            ReadError::Unsupported { tag, bits } => {
+                write!(f, "unsupported format (tag {tag}, {bits} bits)")
+            }
+            ReadError::Format(what) => write!(f, "invalid format: {what}"),
+        }
+    }
+}
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+/// WAV を読み込む．8/16/24/32 ビットの整数と 32/64 ビットの浮動小数点数に対応し，値は [-1, 1] に揃える．
+pub fn read(mut reader: impl Read) -> Result<Sample, ReadError> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(ReadError::NotWav);
+    }
+    let u16_at = |bytes: &[u8], i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+    let mut fmt = None;
+    let mut data = None;
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let len = u32::from_le_bytes(rest[4..8].try_into().unwrap()) as usize;
+        let body = &rest[8..rest.len().min(8 + len)];
+        match &rest[..4] {
+            b"fmt " => fmt = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // チャンクは 2 バイト境界に揃えられている
+        rest = &rest[rest.len().min(8 + len + len % 2)..];
+    }
+    let fmt = fmt
+        .filter(|fmt| fmt.len() >= 16)
+        .ok_or(ReadError::Missing("fmt "))?;
+    let data = data.ok_or(ReadError::Missing("data"))?;
+    let mut tag = u16_at(fmt, 0);
+    let channels = u16_at(fmt, 2);
+    let rate = u32::from_le_bytes(fmt[4..8].try_into().unwrap());
+    let bits = u16_at(fmt, 14);
+    // WAVE_FORMAT_EXTENSIBLE ならサブフォーマットの先頭 2 バイトがタグになる
+    if tag == 0xfffe && fmt.len() >= 26 {
+        tag = u16_at(fmt, 24);
+    }
+    if channels == 0 {
+        return Err(ReadError::Format("no channels"));
+    }
+    if rate == 0 {
+        return Err(ReadError::Format("sample rate of 0"));
+    }
+    let decode: fn(&[u8]) -> f64 = match (tag, bits) {
+        (1, 8) => |b| (b[0] as f64 - 128.) / 128.,
+        (1, 16) => |b| Format::Int16.decode(b),
+        (1, 24) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f64 / 8388608.,
+        (1, 32) => |b| i32::from_le_bytes(b.try_into().unwrap()) as f64 / 2147483648.,
+        (3, 32) => |b| Format::Float32.decode(b),
+        (3, 64) => |b| f64::from_le_bytes(b.try_into().unwrap()),
+        _ => return Err(ReadError::Unsupported { tag, bits }),
+    };
+    let frame_len = channels as usize * bits as usize / 8;
+    let data = &data[..data.len() / frame_len * frame_len];
+    Ok(Sample::new(
+        rate as f64,
+        channels as usize,
+        data.chunks_exact(bits as usize / 8).map(decode).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn encode(sound: &Sound, format: Format) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&mut bytes, sound, 8, 1, 1., format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn int16_round_trip() {
+        let ramp = Sound::App(
+            Func::Sub,
+            vec![
+                Arc::new(Sound::App(
+                    Func::Mul,
+                    vec![
+                        Arc::new(Sound::Const(Box::new(Value::Float(0.25)))),
+                        Arc::new(Sound::T),
+                    ],
+                )),
+                Arc::new(Sound::Const(Box::new(Value::Float(1.)))),
+            ],
+        );
+        let expected: Vec<_> = (0..8).map(|i| i as f64 / 32. - 1.).collect();
+        for format in [Format::Int16, Format::Float32] {
+            let sample = read(&encode(&ramp, format)[..]).unwrap();
+            assert_eq!(sample.channels(), 1);
+            let values: Vec<_> = (0..8).map(|i| sample.at(i as f64 / 8.)).collect();
+            for (value, expected) in values.iter().zip(&expected) {
+                let value = value.as_float().unwrap();
+                assert!((value - expected).abs() < 2e-5, "{value} != {expected}");
+            }
+        }
+        let full = read(&encode(&Sound::Const(Box::new(Value::Float(1.))), Format::Int16)[..]);
+        assert_eq!(full.unwrap().at(0.).into_float().unwrap(), 1.);
+    }
+
+    #[test]
+    fn int16_header_and_clipping() {
+        let mut bytes = Vec::new();
+        let sound = Sound::Const(Box::new(Value::Float(2.)));
+        write(&mut bytes, &sound, 8000, 1, 0.001, Format::Int16).unwrap();
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        assert_eq!(u16_at(&bytes, 20), 1);
+        assert_eq!(u16_at(&bytes, 22), 1);
+        assert_eq!(u32_at(&bytes, 24), 8000);
+        assert_eq!(u32_at(&bytes, 28), 16000);
+        assert_eq!(u16_at(&bytes, 32), 2);
+        assert_eq!(u16_at(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 16);
+        assert!((0..8).all(|i| u16_at(&bytes, 44 + 2 * i) as i16 == i16::MAX));
+    }
+
+    #[test]
+    fn float32_samples() {
+        let mut bytes = Vec::new();
+        write(&mut bytes, &Sound::T, 4, 1, 1., Format::Float32).unwrap();
+        assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+        assert_eq!(u32_at(&bytes, 16), 18);
+        assert_eq!(u16_at(&bytes, 20), 3);
+        assert_eq!(u16_at(&bytes, 34), 32);
+        assert_eq!(&bytes[38..42], b"fact");
+        assert_eq!(u32_at(&bytes, 46), 4);
+        assert_eq!(&bytes[50..54], b"data");
+        assert_eq!(u32_at(&bytes, 54), 16);
+        let samples: Vec<_> = bytes[58..]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(samples, [0., 0.25, 0.5, 0.75]);
+    }
+
+    #[test]
+    fn stereo_frames_are_interleaved() {
+        let mut bytes = Vec::new();
+        let sound = Sound::Const(Box::new(Value::Frame(Arc::new([0.5, -0.5]))));
+        write(&mut bytes, &sound, 4, 2, 0.5, Format::Int16).unwrap();
+        assert_eq!(u16_at(&bytes, 22), 2);
+        assert_eq!(u32_at(&bytes, 28), 16);
+        assert_eq!(u16_at(&bytes, 32), 4);
+        assert_eq!(u32_at(&bytes, 40), 8);
+        let samples: Vec<_> = (0..4).map(|i| u16_at(&bytes, 44 + 2 * i) as i16).collect();
+        assert_eq!(samples, [16384, -16384, 16384, -16384]);
+    }
+
+    #[test]
+    fn non_float_sample_is_an_error() {
+        let sound = Sound::Const(Box::new(Value::Func(Func::Sin)));
+        let result = write(Vec::new(), &sound, 8000, 1, 0.001, Format::Int16);
+        assert!(matches!(
+            result,
+            Err(Error::Sound(crate::Error::NotFloat(0, Value::Func(_))))
+        ));
+    }
+
+    #[test]
+    fn unsupported_files() {
+        assert!(matches!(read(&b"RIFX"[..]), Err(ReadError::NotWav)));
+        let bytes = encode(&Sound::T, Format::Int16);
+        assert!(matches!(
+            read(&bytes[..36]),
+            Err(ReadError::Missing("data"))
+        ));
+        let mut bytes = bytes;
+        bytes[34] = 12;
+        assert!(matches!(
+            read(&bytes[..]),
+            Err(ReadError::Unsupported { tag: 1, bits: 12 })
+        ));
+    }
+
+    #[test]
+    fn zero_rate_is_rejected() {
+        let mut bytes = encode(&Sound::T, Format::Int16);
+        bytes[24..28].copy_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(read(&bytes[..]), Err(ReadError::Format(_))));
+    }
+
+    #[test]
+    fn too_long_data_is_rejected() {
+        let mut sink = Sink::new(io::sink(), Format::Float32);
+        let err = sink.start(44100, 2, usize::MAX / 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(sink.start(44100, 2, 1 << 29).is_err());
+        let err = sink.start(44100, u16::MAX, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+}
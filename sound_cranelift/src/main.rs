@@ -1,7 +1,7 @@
 use cranelift::{codegen::ir::immediates::Offset32, prelude::*};
 use cranelift_module::{DataDescription, Linkage, Module};
 
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug)]
 enum IRValue {
@@ -18,8 +18,8 @@ impl IRValue {
 enum Sound {
     T,
     Const(IRValue),
-    App(Func, Vec<Rc<Sound>>),
-    Trim(f64, f64, Rc<Sound>),
+    App(Func, Vec<Arc<Sound>>),
+    Trim(f64, f64, Arc<Sound>),
 }
 impl Sound {
     fn translate(&self, builder: &mut FunctionBuilder, t: Value) -> Value {
@@ -33,8 +33,15 @@ impl Sound {
                     .collect();
                 func.translate(builder, args)
             }
-            Sound::Trim(_from, _to, _sound) => {
-                todo!();
+            Sound::Trim(from, to, sound) => {
+                let value = sound.translate(builder, t);
+                let from = builder.ins().f64const(*from);
+                let to = builder.ins().f64const(*to);
+                let after_from = builder.ins().fcmp(FloatCC::GreaterThanOrEqual, t, from);
+                let before_to = builder.ins().fcmp(FloatCC::LessThan, t, to);
+                let inside = builder.ins().band(after_from, before_to);
+                let zero = builder.ins().f64const(0.);
+                builder.ins().select(inside, value, zero)
             }
         }
     }
@@ -67,24 +74,24 @@ impl BuiltinFunc {
 
 fn main() {
     let mut sounds = HashMap::new();
-    sounds.insert("T", Rc::new(Sound::T));
-    sounds.insert("2", Rc::new(Sound::Const(IRValue::Float(2.))));
+    sounds.insert("T", Arc::new(Sound::T));
+    sounds.insert("2", Arc::new(Sound::Const(IRValue::Float(2.))));
     {
         let args = vec![sounds["T"].clone(), sounds["2"].clone()];
         sounds.insert(
             "X",
-            Rc::new(Sound::App(Func::Builtin(BuiltinFunc::MulFloat), args)),
+            Arc::new(Sound::App(Func::Builtin(BuiltinFunc::MulFloat), args)),
         );
     }
     {
         let arg = sounds["X"].clone();
-        sounds.insert("Y", Rc::new(Sound::Trim(1., 2., arg)));
+        sounds.insert("Y", Arc::new(Sound::Trim(1., 2., arg)));
     }
     {
         let args = vec![sounds["X"].clone(), sounds["Y"].clone()];
         sounds.insert(
             "Z",
-            Rc::new(Sound::App(Func::Builtin(BuiltinFunc::AddFloat), args)),
+            Arc::new(Sound::App(Func::Builtin(BuiltinFunc::AddFloat), args)),
         );
     }
     for (name, sound) in &sounds {